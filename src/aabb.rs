@@ -0,0 +1,72 @@
+use crate::{
+    ray::Ray,
+    vec::{FloatType, Point3},
+};
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub minimum: Point3,
+    pub maximum: Point3,
+}
+
+impl Aabb {
+    pub fn new(minimum: Point3, maximum: Point3) -> Self {
+        Self { minimum, maximum }
+    }
+
+    /// Box that contains nothing and that no ray can hit, the identity for `surrounding_box`
+    pub fn empty() -> Self {
+        Self::new(
+            Point3::new_eq(FloatType::INFINITY),
+            Point3::new_eq(FloatType::NEG_INFINITY),
+        )
+    }
+
+    /// Returns true if the ray enters the box somewhere in [t_min, t_max]
+    pub fn hit(&self, r: &Ray, mut t_min: FloatType, mut t_max: FloatType) -> bool {
+        for axis in 0..3 {
+            let inv_d = 1.0 / r.direction[axis];
+            let mut t0 = (self.minimum[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.maximum[axis] - r.origin[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Smallest box containing both boxes
+    pub fn surrounding_box(&self, other: &Aabb) -> Aabb {
+        Aabb::new(
+            self.minimum.min(&other.minimum),
+            self.maximum.max(&other.maximum),
+        )
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.minimum + self.maximum)
+    }
+
+    pub fn surface_area(&self) -> FloatType {
+        let extent = self.maximum - self.minimum;
+        2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x)
+    }
+
+    /// Index of the axis along which the box is the longest
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.maximum - self.minimum;
+        if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::fmt;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable, HittableList},
+    ray::Ray,
+    vec::{FloatType, Point3},
+};
+
+/// Relative cost of testing a ray against the two children of a node
+const TRAVERSAL_COST: FloatType = 0.125;
+/// Relative cost of intersecting a ray with one primitive
+const INTERSECTION_COST: FloatType = 1.0;
+/// Nodes with more primitives are always split, even if the SAH prefers a leaf
+const MAX_LEAF_SIZE: usize = 4;
+
+/// How the primitives of a node are partitioned into its two children
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitMethod {
+    /// Halve the primitives at the median centroid along the axis of largest centroid spread
+    Median,
+    /// Binned surface area heuristic with the given number of bins per axis
+    Sah { bins: usize },
+}
+
+impl Default for SplitMethod {
+    fn default() -> Self {
+        SplitMethod::Sah { bins: 16 }
+    }
+}
+
+/// Bounding volume hierarchy over a set of objects
+///
+/// Every node stores the box around all of its children, so whole subtrees can be skipped
+/// with a single box test instead of testing every object like `HittableList` does.
+pub struct BvhNode {
+    bbox: Aabb,
+    contents: BvhContents,
+}
+
+enum BvhContents {
+    Leaf(Vec<Box<dyn Hittable>>),
+    Split {
+        left: Box<BvhNode>,
+        right: Box<BvhNode>,
+    },
+}
+
+/// An object together with its (cached) bounding box
+struct BvhPrimitive {
+    bbox: Aabb,
+    centroid: Point3,
+    object: Box<dyn Hittable>,
+}
+
+impl BvhNode {
+    /// Builds the hierarchy from all objects of the list
+    ///
+    /// Panics if one of the objects is unbounded.
+    pub fn with_split_method(list: HittableList, split_method: SplitMethod) -> Self {
+        let primitives = list
+            .objects
+            .into_iter()
+            .map(|object| {
+                let bbox = object
+                    .bounding_box()
+                    .expect("No bounding box in BvhNode constructor");
+                BvhPrimitive {
+                    bbox,
+                    centroid: bbox.centroid(),
+                    object,
+                }
+            })
+            .collect();
+        Self::build(primitives, split_method)
+    }
+
+    fn build(mut primitives: Vec<BvhPrimitive>, split_method: SplitMethod) -> Self {
+        let bbox = primitives
+            .iter()
+            .fold(Aabb::empty(), |acc, p| acc.surrounding_box(&p.bbox));
+
+        if primitives.len() <= 1 {
+            return Self::leaf(bbox, primitives);
+        }
+
+        let centroid_bounds = primitives.iter().fold(Aabb::empty(), |acc, p| {
+            acc.surrounding_box(&Aabb::new(p.centroid, p.centroid))
+        });
+        let axis = centroid_bounds.longest_axis();
+        let right = if centroid_bounds.maximum[axis] <= centroid_bounds.minimum[axis] {
+            // All centroids coincide, no position separates them. Halve them by count so the
+            // leaves stay small.
+            if primitives.len() <= MAX_LEAF_SIZE {
+                return Self::leaf(bbox, primitives);
+            }
+            primitives.split_off(primitives.len() / 2)
+        } else {
+            match split_method {
+                SplitMethod::Median => Self::split_median(&mut primitives, axis),
+                SplitMethod::Sah { bins } => {
+                    match Self::split_sah(&mut primitives, &bbox, &centroid_bounds, bins) {
+                        Some(right) => right,
+                        None => return Self::leaf(bbox, primitives),
+                    }
+                }
+            }
+        };
+
+        BvhNode {
+            bbox,
+            contents: BvhContents::Split {
+                left: Box::new(Self::build(primitives, split_method)),
+                right: Box::new(Self::build(right, split_method)),
+            },
+        }
+    }
+
+    fn leaf(bbox: Aabb, primitives: Vec<BvhPrimitive>) -> Self {
+        BvhNode {
+            bbox,
+            contents: BvhContents::Leaf(primitives.into_iter().map(|p| p.object).collect()),
+        }
+    }
+
+    /// Moves the upper half of the primitives (by centroid along axis) into the returned vector
+    fn split_median(primitives: &mut Vec<BvhPrimitive>, axis: usize) -> Vec<BvhPrimitive> {
+        let mid = primitives.len() / 2;
+        primitives.select_nth_unstable_by(mid, |a, b| {
+            a.centroid[axis]
+                .partial_cmp(&b.centroid[axis])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        primitives.split_off(mid)
+    }
+
+    /// Partitions the primitives at the cheapest bin boundary according to the surface area
+    /// heuristic and returns the primitives of the right side
+    ///
+    /// Returns None if turning the node into a leaf is cheaper than any split.
+    fn split_sah(
+        primitives: &mut Vec<BvhPrimitive>,
+        bbox: &Aabb,
+        centroid_bounds: &Aabb,
+        bins: usize,
+    ) -> Option<Vec<BvhPrimitive>> {
+        let bins = bins.max(2);
+        let bin_index = |centroid: &Point3, axis: usize| {
+            let extent = centroid_bounds.maximum[axis] - centroid_bounds.minimum[axis];
+            let offset = (centroid[axis] - centroid_bounds.minimum[axis]) / extent;
+            ((offset * bins as FloatType) as usize).min(bins - 1)
+        };
+
+        // (cost, axis, index of the first bin on the right side)
+        let mut best: Option<(FloatType, usize, usize)> = None;
+        for axis in 0..3 {
+            if centroid_bounds.maximum[axis] <= centroid_bounds.minimum[axis] {
+                continue;
+            }
+
+            let mut counts = vec![0usize; bins];
+            let mut bounds = vec![Aabb::empty(); bins];
+            for p in primitives.iter() {
+                let b = bin_index(&p.centroid, axis);
+                counts[b] += 1;
+                bounds[b] = bounds[b].surrounding_box(&p.bbox);
+            }
+
+            // Sweep from the right to get the area and count of every right side
+            let mut right_area = vec![0.0; bins];
+            let mut right_count = vec![0usize; bins];
+            let mut acc_bounds = Aabb::empty();
+            let mut acc_count = 0;
+            for b in (1..bins).rev() {
+                acc_bounds = acc_bounds.surrounding_box(&bounds[b]);
+                acc_count += counts[b];
+                right_area[b] = acc_bounds.surface_area();
+                right_count[b] = acc_count;
+            }
+
+            let mut acc_bounds = Aabb::empty();
+            let mut acc_count = 0;
+            for b in 1..bins {
+                acc_bounds = acc_bounds.surrounding_box(&bounds[b - 1]);
+                acc_count += counts[b - 1];
+                if acc_count == 0 || right_count[b] == 0 {
+                    continue;
+                }
+                let cost = acc_bounds.surface_area() * acc_count as FloatType
+                    + right_area[b] * right_count[b] as FloatType;
+                if best.map_or(true, |(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, b));
+                }
+            }
+        }
+
+        let (cost, axis, split_bin) = best?;
+        let split_cost = TRAVERSAL_COST
+            + INTERSECTION_COST * cost / bbox.surface_area().max(FloatType::MIN_POSITIVE);
+        let leaf_cost = INTERSECTION_COST * primitives.len() as FloatType;
+        if primitives.len() <= MAX_LEAF_SIZE && leaf_cost <= split_cost {
+            return None;
+        }
+
+        let (left, right): (Vec<_>, Vec<_>) = primitives
+            .drain(..)
+            .partition(|p| bin_index(&p.centroid, axis) < split_bin);
+        *primitives = left;
+        Some(right)
+    }
+
+    /// Collects node counts, depth, leaf sizes and the estimated traversal cost of the tree
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            min_leaf_size: usize::MAX,
+            ..Default::default()
+        };
+        let root_area = self.bbox.surface_area().max(FloatType::MIN_POSITIVE);
+        self.collect_stats(&mut stats, 1, root_area);
+        if stats.leaf_count == 0 {
+            stats.min_leaf_size = 0;
+        }
+        stats
+    }
+
+    fn collect_stats(&self, stats: &mut BvhStats, depth: usize, root_area: FloatType) {
+        stats.node_count += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        // Probability that a random ray hitting the root also hits this node
+        let probability = self.bbox.surface_area().max(0.0) / root_area;
+
+        match &self.contents {
+            BvhContents::Leaf(objects) => {
+                let size = objects.len();
+                stats.leaf_count += 1;
+                stats.primitive_count += size;
+                stats.min_leaf_size = stats.min_leaf_size.min(size);
+                stats.max_leaf_size = stats.max_leaf_size.max(size);
+                if stats.leaf_size_histogram.len() <= size {
+                    stats.leaf_size_histogram.resize(size + 1, 0);
+                }
+                stats.leaf_size_histogram[size] += 1;
+                stats.estimated_cost += probability * INTERSECTION_COST * size as FloatType;
+            }
+            BvhContents::Split { left, right } => {
+                stats.estimated_cost += probability * TRAVERSAL_COST;
+                left.collect_stats(stats, depth + 1, root_area);
+                right.collect_stats(stats, depth + 1, root_area);
+            }
+        }
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+
+        match &self.contents {
+            BvhContents::Leaf(objects) => {
+                let mut anything_hit = None;
+                let mut closest_so_far = t_max;
+                for object in objects.iter() {
+                    if let Some(hit) = object.hit(r, t_min, closest_so_far) {
+                        closest_so_far = hit.t;
+                        anything_hit = Some(hit);
+                    }
+                }
+                anything_hit
+            }
+            BvhContents::Split { left, right } => {
+                let hit_left = left.hit(r, t_min, t_max);
+                let closest_so_far = hit_left.as_ref().map_or(t_max, |hit| hit.t);
+                right.hit(r, t_min, closest_so_far).or(hit_left)
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+/// Shape and quality numbers of a built BVH
+#[derive(Debug, Clone, Default)]
+pub struct BvhStats {
+    /// Interior nodes and leaves
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+    /// Depth of the deepest leaf, the root has depth 1
+    pub max_depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    /// Number of leaves per leaf size
+    pub leaf_size_histogram: Vec<usize>,
+    /// Expected cost of a ray hitting the root according to the surface area heuristic
+    pub estimated_cost: FloatType,
+}
+
+impl BvhStats {
+    pub fn average_leaf_size(&self) -> FloatType {
+        if self.leaf_count == 0 {
+            0.0
+        } else {
+            self.primitive_count as FloatType / self.leaf_count as FloatType
+        }
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "BVH: {} primitives, {} nodes, {} leaves, depth {}",
+            self.primitive_count, self.node_count, self.leaf_count, self.max_depth
+        )?;
+        writeln!(
+            f,
+            "     leaf size min/avg/max: {}/{:.2}/{}",
+            self.min_leaf_size,
+            self.average_leaf_size(),
+            self.max_leaf_size
+        )?;
+        let histogram = self
+            .leaf_size_histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(size, count)| format!("{}:{}", size, count))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(f, "     leaf size histogram: {}", histogram)?;
+        write!(
+            f,
+            "     estimated traversal cost: {:.3}",
+            self.estimated_cost
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Material,
+        sphere::Sphere,
+        utils::{random_float_range, reseed_rng},
+        vec::Vector3,
+    };
+
+    /// Random spheres, each with a dielectric whose index is the position in the list
+    fn random_spheres(count: usize) -> HittableList {
+        reseed_rng(7);
+        let mut list = HittableList::default();
+        for index in 0..count {
+            list.add(Box::new(Sphere::new(
+                Point3::random_range(-10.0, 10.0),
+                random_float_range(0.1, 1.5),
+                Material::Dielectric(index as FloatType),
+            )));
+        }
+        list
+    }
+
+    fn random_rays(count: usize) -> Vec<Ray> {
+        (0..count)
+            .map(|_| {
+                let origin = Point3::random_range(-15.0, 15.0);
+                let target = Point3::random_range(-5.0, 5.0);
+                Ray::new(origin, target - origin)
+            })
+            .collect()
+    }
+
+    /// Distance and sphere index of the closest hit
+    fn closest(world: &impl Hittable, r: &Ray) -> Option<(FloatType, usize)> {
+        world
+            .hit(r, 0.001, FloatType::MAX)
+            .map(|hit| match hit.material {
+                Material::Dielectric(index) => (hit.t, *index as usize),
+                _ => unreachable!("Only dielectric spheres"),
+            })
+    }
+
+    #[test]
+    fn same_hits_as_list() {
+        let list = random_spheres(200);
+        let rays = random_rays(2000);
+        for split_method in [SplitMethod::Median, SplitMethod::default()] {
+            let bvh = BvhNode::with_split_method(random_spheres(200), split_method);
+            let mut hits = 0;
+            for r in &rays {
+                let expected = closest(&list, r);
+                assert_eq!(closest(&bvh, r), expected, "{:?}", split_method);
+                hits += expected.is_some() as usize;
+            }
+            assert!(hits > rays.len() / 4, "Too few rays hit: {}", hits);
+        }
+    }
+
+    fn spheres_at(centers: &[Point3]) -> HittableList {
+        let mut list = HittableList::default();
+        for (index, center) in centers.iter().enumerate() {
+            list.add(Box::new(Sphere::new(
+                *center,
+                0.5,
+                Material::Dielectric(index as FloatType),
+            )));
+        }
+        list
+    }
+
+    #[test]
+    fn sah_splits_between_clusters() {
+        // Few spheres on the left and many on the right, so the median is in the right one
+        let centers: Vec<Point3> = (0..3)
+            .map(|i| Point3::new(-20.0 + i as FloatType, 0.0, 0.0))
+            .chain((0..13).map(|i| Point3::new(10.0 + i as FloatType, 0.0, 0.0)))
+            .collect();
+        let bvh = BvhNode::with_split_method(spheres_at(&centers), SplitMethod::default());
+        let (left, right) = match &bvh.contents {
+            BvhContents::Split { left, right } => (left, right),
+            BvhContents::Leaf(_) => panic!("Root wasn't split"),
+        };
+        assert!(left.bbox.maximum.x < 0.0 && right.bbox.minimum.x > 0.0);
+        assert_eq!(left.stats().primitive_count, 3);
+        assert_eq!(right.stats().primitive_count, 13);
+    }
+
+    #[test]
+    fn coinciding_centroids_keep_leaves_small() {
+        let centers = vec![Point3::new(1.0, 2.0, 3.0); 50];
+        for split_method in [SplitMethod::Median, SplitMethod::default()] {
+            let bvh = BvhNode::with_split_method(spheres_at(&centers), split_method);
+            let stats = bvh.stats();
+            assert_eq!(stats.primitive_count, 50);
+            assert!(stats.max_leaf_size <= MAX_LEAF_SIZE, "{:?}", stats);
+
+            let r = Ray::new(Point3::new(1.0, 2.0, -1.0), Vector3::new(0.0, 0.0, 1.0));
+            assert_eq!(closest(&bvh, &r).map(|(t, _)| t), Some(3.5));
+        }
+    }
+
+    #[test]
+    fn stats_counts() {
+        // The median split halves down to single spheres: 8 leaves in a tree of depth 4
+        let centers: Vec<Point3> = (0..8)
+            .map(|i| Point3::new(2.0 * i as FloatType, 0.0, 0.0))
+            .collect();
+        let stats = BvhNode::with_split_method(spheres_at(&centers), SplitMethod::Median).stats();
+        assert_eq!(stats.primitive_count, 8);
+        assert_eq!(stats.leaf_count, 8);
+        assert_eq!(stats.node_count, 15);
+        assert_eq!(stats.max_depth, 4);
+        assert_eq!((stats.min_leaf_size, stats.max_leaf_size), (1, 1));
+        assert_eq!(stats.leaf_size_histogram, vec![0, 8]);
+        assert_eq!(stats.average_leaf_size(), 1.0);
+        assert!(stats.estimated_cost > INTERSECTION_COST);
+    }
+
+    #[test]
+    fn empty_list() {
+        let bvh = BvhNode::with_split_method(HittableList::default(), SplitMethod::default());
+        for r in random_rays(100) {
+            assert!(bvh.hit(&r, 0.001, FloatType::MAX).is_none());
+        }
+        let stats = bvh.stats();
+        assert_eq!(stats.primitive_count, 0);
+        assert_eq!(stats.min_leaf_size, 0);
+    }
+
+    #[test]
+    fn single_object() {
+        let mut list = HittableList::default();
+        list.add(Box::new(Sphere::new(
+            Point3::new(1.0, 2.0, 3.0),
+            0.5,
+            Material::Dielectric(0.0),
+        )));
+        let bvh = BvhNode::with_split_method(list, SplitMethod::default());
+
+        let r = Ray::new(Point3::new(1.0, 2.0, -1.0), Vector3::new(0.0, 0.0, 2.0));
+        assert_eq!(closest(&bvh, &r), Some((1.75, 0)));
+        let r = Ray::new(Point3::new(1.0, 3.0, -1.0), Vector3::new(0.0, 0.0, 1.0));
+        assert_eq!(closest(&bvh, &r), None);
+        assert_eq!(bvh.stats().leaf_count, 1);
+    }
+}
@@ -10,6 +10,7 @@ pub struct Camera {
     vertical: Vector3,
     u: Vector3,
     v: Vector3,
+    #[allow(unused)]
     w: Vector3,
     lens_radius: FloatType,
 }
@@ -0,0 +1,382 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{
+    bvh::SplitMethod,
+    image::{BitDepth, EncodeOptions, ExrCompression, ExrPixelType, ImageFormat},
+    scene::ImageSettings,
+    vec::FloatType,
+};
+
+pub const USAGE: &str = "\
+Usage: ray-tracing-in-one-weekend [OPTIONS]
+
+Options:
+  --scene <FILE|NAME>    Scene file (.toml), model (.obj, .ply) or name of a builtin scene
+                         [default: random]
+  --list-scenes          Print the builtin scenes and exit
+  --width <PIXELS>       Image width, keeps the aspect ratio of the scene unless --height is given
+  --height <PIXELS>      Image height, keeps the aspect ratio of the scene unless --width is given
+  --spp <SAMPLES>        Samples per pixel
+  --max-depth <BOUNCES>  Maximum number of bounces per path
+  --output <FILE>        Output image, format by extension (.png, .ppm, .hdr, .pfm, .exr)
+                         [default: PPM on stdout]
+  --bit-depth <8|16>     Bits per channel of PNG and PPM output [default: 8]
+  --exr-compression <none|rle|zips|zip>
+                         Compression of EXR output [default: zip]
+  --exr-type <half|float>
+                         Sample type of EXR output, which also gets Z and N.X/N.Y/N.Z
+                         channels with camera distance and normal [default: half]
+  --threads <COUNT>      Number of render threads [default: one per core]
+  --seed <SEED>          Seed for deterministic renders
+  --bvh <median|sah>     BVH split method [default: sah]
+  --stats                Print statistics of the built BVH
+  -h, --help             Print this help and exit";
+
+/// Invalid command line
+#[derive(Debug)]
+pub struct CliError {
+    message: String,
+}
+
+impl CliError {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// What the binary was asked to do
+pub enum Command {
+    Render(Options),
+    ListScenes,
+    Help,
+}
+
+/// Render options, settings that are None come from the scene
+#[derive(Debug, Default)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples_per_pixel: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub output: Option<PathBuf>,
+    pub format: ImageFormat,
+    pub encode: EncodeOptions,
+    pub threads: Option<usize>,
+    pub seed: Option<u64>,
+    pub split_method: SplitMethod,
+    /// Print the BVH statistics before rendering
+    pub stats: bool,
+}
+
+impl Options {
+    /// Overrides the image settings of the scene with the ones given on the command line
+    pub fn apply(&self, image: &mut ImageSettings) {
+        match (self.width, self.height) {
+            (Some(width), Some(height)) => {
+                image.width = width;
+                image.aspect_ratio = width as FloatType / height as FloatType;
+            }
+            (Some(width), None) => image.width = width,
+            (None, Some(height)) => {
+                image.width = ((height as FloatType * image.aspect_ratio).round() as usize).max(1)
+            }
+            (None, None) => {}
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            image.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            image.max_depth = max_depth;
+        }
+    }
+}
+
+/// Parses the arguments without the program name
+///
+/// Values can be given as `--option value` or `--option=value`.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, CliError> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let takes_value = !matches!(flag.as_str(), "--list-scenes" | "--stats" | "-h" | "--help");
+        let value = if takes_value {
+            match inline_value {
+                Some(value) => value,
+                None => args
+                    .next()
+                    .ok_or_else(|| CliError::new(format!("{} needs a value", flag)))?,
+            }
+        } else if inline_value.is_some() {
+            return Err(CliError::new(format!("{} doesn't take a value", flag)));
+        } else {
+            String::new()
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--list-scenes" => return Ok(Command::ListScenes),
+            "--stats" => options.stats = true,
+            "--scene" => options.scene = Some(value),
+            "--width" => options.width = Some(positive(&flag, &value)?),
+            "--height" => options.height = Some(positive(&flag, &value)?),
+            "--spp" => options.samples_per_pixel = Some(positive(&flag, &value)?),
+            "--max-depth" => options.max_depth = Some(positive(&flag, &value)?),
+            "--threads" => options.threads = Some(positive(&flag, &value)?),
+            "--seed" => {
+                options.seed = Some(value.parse().map_err(|_| {
+                    CliError::new(format!(
+                        "--seed expects a non-negative integer, got '{}'",
+                        value
+                    ))
+                })?)
+            }
+            "--output" => {
+                let path = PathBuf::from(&value);
+                options.format = ImageFormat::from_path(&path).ok_or_else(|| {
+                    CliError::new(format!(
+                        "--output '{}' has an unsupported format, expected a .png, .ppm, .hdr, .pfm or .exr file",
+                        value
+                    ))
+                })?;
+                options.output = Some(path);
+            }
+            "--bit-depth" => {
+                options.encode.bit_depth = match value.as_str() {
+                    "8" => BitDepth::Eight,
+                    "16" => BitDepth::Sixteen,
+                    _ => {
+                        return Err(CliError::new(format!(
+                            "--bit-depth expects 8 or 16, got '{}'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--exr-compression" => {
+                options.encode.exr_compression = match value.as_str() {
+                    "none" => ExrCompression::None,
+                    "rle" => ExrCompression::Rle,
+                    "zips" => ExrCompression::Zips,
+                    "zip" => ExrCompression::Zip,
+                    _ => {
+                        return Err(CliError::new(format!(
+                            "--exr-compression expects none, rle, zips or zip, got '{}'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--exr-type" => {
+                options.encode.exr_pixel_type = match value.as_str() {
+                    "half" => ExrPixelType::Half,
+                    "float" => ExrPixelType::Float,
+                    _ => {
+                        return Err(CliError::new(format!(
+                            "--exr-type expects half or float, got '{}'",
+                            value
+                        )))
+                    }
+                }
+            }
+            "--bvh" => {
+                options.split_method = match value.as_str() {
+                    "median" => SplitMethod::Median,
+                    "sah" => SplitMethod::default(),
+                    _ => {
+                        return Err(CliError::new(format!(
+                            "--bvh expects median or sah, got '{}'",
+                            value
+                        )))
+                    }
+                }
+            }
+            _ => return Err(CliError::new(format!("unknown option '{}'", flag))),
+        }
+    }
+
+    Ok(Command::Render(options))
+}
+
+fn positive(flag: &str, value: &str) -> Result<usize, CliError> {
+    match value.parse() {
+        Ok(number) if number > 0 => Ok(number),
+        _ => Err(CliError::new(format!(
+            "{} expects a positive integer, got '{}'",
+            flag, value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Render(options)) => options,
+            Ok(_) => panic!("{:?} didn't parse into render options", args),
+            Err(error) => panic!("{:?}: {}", args, error),
+        }
+    }
+
+    fn error_message(args: &[&str]) -> String {
+        parse(args)
+            .err()
+            .unwrap_or_else(|| panic!("{:?} should have failed", args))
+            .to_string()
+    }
+
+    #[test]
+    fn inline_and_separate_values() {
+        for args in [
+            &["--width", "640", "--scene", "cornell", "--seed", "7"][..],
+            &["--width=640", "--scene=cornell", "--seed=7"],
+            &["--width=640", "--scene", "cornell", "--seed=7"],
+        ] {
+            let options = options(args);
+            assert_eq!(options.width, Some(640), "{:?}", args);
+            assert_eq!(options.scene.as_deref(), Some("cornell"), "{:?}", args);
+            assert_eq!(options.seed, Some(7), "{:?}", args);
+        }
+        // Only the first = separates the value
+        assert_eq!(
+            options(&["--scene=a=b.toml"]).scene.as_deref(),
+            Some("a=b.toml")
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let options = options(&[]);
+        assert_eq!(options.scene, None);
+        assert_eq!(options.format, ImageFormat::Ppm);
+        assert_eq!(options.split_method, SplitMethod::default());
+        assert!(!options.stats);
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(error_message(&["--width"]), "--width needs a value");
+        assert_eq!(
+            error_message(&["--spp", "10", "--output"]),
+            "--output needs a value"
+        );
+    }
+
+    #[test]
+    fn zero_width() {
+        assert_eq!(
+            error_message(&["--width", "0"]),
+            "--width expects a positive integer, got '0'"
+        );
+        assert_eq!(
+            error_message(&["--height=-3"]),
+            "--height expects a positive integer, got '-3'"
+        );
+    }
+
+    #[test]
+    fn output_format() {
+        let options = options(&["--output", "render.EXR"]);
+        assert_eq!(options.format, ImageFormat::Exr);
+        assert_eq!(options.output, Some(PathBuf::from("render.EXR")));
+        assert_eq!(
+            error_message(&["--output", "render.jpg"]),
+            "--output 'render.jpg' has an unsupported format, expected a .png, .ppm, .hdr, .pfm or .exr file"
+        );
+        assert!(error_message(&["--output=render"]).contains("unsupported format"));
+    }
+
+    #[test]
+    fn flags_without_values() {
+        assert!(matches!(parse(&["--list-scenes"]), Ok(Command::ListScenes)));
+        assert!(matches!(parse(&["--width", "5", "-h"]), Ok(Command::Help)));
+        assert!(options(&["--stats"]).stats);
+        assert_eq!(
+            error_message(&["--list-scenes=x"]),
+            "--list-scenes doesn't take a value"
+        );
+        assert_eq!(
+            error_message(&["--stats=yes"]),
+            "--stats doesn't take a value"
+        );
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert_eq!(error_message(&["--widht", "5"]), "unknown option '--widht'");
+        assert_eq!(
+            error_message(&["--bvh", "octree"]),
+            "--bvh expects median or sah, got 'octree'"
+        );
+    }
+
+    #[test]
+    fn apply_width_only() {
+        let mut image = ImageSettings::default();
+        options(&["--width", "600"]).apply(&mut image);
+        assert_eq!(image.width, 600);
+        assert_eq!(image.aspect_ratio, 3.0 / 2.0);
+        assert_eq!(image.height(), 400);
+    }
+
+    #[test]
+    fn apply_height_only() {
+        let mut image = ImageSettings::default();
+        options(&["--height", "100"]).apply(&mut image);
+        assert_eq!(image.width, 150);
+        assert_eq!(image.aspect_ratio, 3.0 / 2.0);
+        assert_eq!(image.height(), 100);
+    }
+
+    #[test]
+    fn apply_width_and_height() {
+        let mut image = ImageSettings::default();
+        options(&[
+            "--width",
+            "300",
+            "--height",
+            "300",
+            "--spp",
+            "4",
+            "--max-depth",
+            "3",
+        ])
+        .apply(&mut image);
+        assert_eq!((image.width, image.height()), (300, 300));
+        assert_eq!(image.aspect_ratio, 1.0);
+        assert_eq!((image.samples_per_pixel, image.max_depth), (4, 3));
+    }
+
+    #[test]
+    fn apply_nothing() {
+        let mut image = ImageSettings::default();
+        options(&[]).apply(&mut image);
+        let defaults = ImageSettings::default();
+        assert_eq!(image.width, defaults.width);
+        assert_eq!(image.aspect_ratio, defaults.aspect_ratio);
+        assert_eq!(image.samples_per_pixel, defaults.samples_per_pixel);
+    }
+}
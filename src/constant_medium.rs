@@ -0,0 +1,206 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    utils::random_float,
+    vec::{FloatType, Vector3},
+};
+
+/// Volume of constant density filling a boundary, e.g. fog or smoke
+///
+/// Rays scatter at exponentially distributed distances inside and continue through the
+/// boundary otherwise. The boundary must be closed and convex, a ray crossing it leaves at
+/// its second intersection. Behind concave parts of a mesh the medium is missing, rays that
+/// never find a second intersection pass an open mesh unaffected.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: FloatType,
+    /// Usually `Material::Isotropic`
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    /// `density` is the chance to scatter per unit of length
+    pub fn new(boundary: Box<dyn Hittable>, density: FloatType, phase_function: Material) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
+        // Both crossings of the boundary along the whole line, also for rays starting inside
+        let entry = self
+            .boundary
+            .hit(r, FloatType::NEG_INFINITY, FloatType::INFINITY)?;
+        let exit = self
+            .boundary
+            .hit(r, entry.t + 0.0001, FloatType::INFINITY)?;
+
+        let t_entry = entry.t.max(t_min).max(0.0);
+        let t_exit = exit.t.min(t_max);
+        if t_entry >= t_exit {
+            return None;
+        }
+
+        let ray_length = r.direction.length();
+        let distance_inside = (t_exit - t_entry) * ray_length;
+        let hit_distance = self.neg_inv_density * random_float().ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_entry + hit_distance / ray_length;
+        Some(HitRecord {
+            point: r.at(t),
+            // Volumes have no surface, the phase function ignores the normal
+            normal: Vector3::new(1.0, 0.0, 0.0),
+            material: &self.phase_function,
+            t,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vector3::new_eq(0.0),
+            dpdv: Vector3::new_eq(0.0),
+            color: None,
+            facing_front: true,
+            light: None,
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        hittable::HittableList,
+        triangle::Triangle,
+        utils::reseed_rng,
+        vec::{Color, Point3},
+    };
+
+    /// Box between 0 and `thickness` along x and wide along y and z, made of 12 triangles
+    fn slab(thickness: FloatType) -> HittableList {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { 0.0 } else { thickness },
+                if i & 2 == 0 { -100.0 } else { 100.0 },
+                if i & 4 == 0 { -100.0 } else { 100.0 },
+            )
+        };
+        // Corner indices of the faces, two triangles each
+        let faces = [
+            [0, 2, 6, 4],
+            [1, 3, 7, 5],
+            [0, 1, 5, 4],
+            [2, 3, 7, 6],
+            [0, 1, 3, 2],
+            [4, 5, 7, 6],
+        ];
+        let mut list = HittableList::default();
+        for [a, b, c, d] in faces {
+            for [p, q, r] in [[a, b, c], [a, c, d]] {
+                let material = Material::Lambertian(Color::new_eq(0.5).into());
+                list.add(Box::new(Triangle::new(
+                    corner(p),
+                    corner(q),
+                    corner(r),
+                    material,
+                )));
+            }
+        }
+        list
+    }
+
+    fn fog(thickness: FloatType, density: FloatType) -> ConstantMedium {
+        let phase_function = Material::Isotropic(Color::new_eq(1.0).into());
+        ConstantMedium::new(Box::new(slab(thickness)), density, phase_function)
+    }
+
+    /// Share of the rays that pass the medium without scattering
+    fn transmitted(
+        medium: &ConstantMedium,
+        ray: &Ray,
+        t_max: FloatType,
+        count: usize,
+    ) -> FloatType {
+        let passed = (0..count)
+            .filter(|_| medium.hit(ray, 0.001, t_max).is_none())
+            .count();
+        passed as FloatType / count as FloatType
+    }
+
+    fn assert_transmittance(actual: FloatType, distance: FloatType, density: FloatType) {
+        let expected = (-density * distance).exp();
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{} of the rays passed, expected {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn slab_transmittance() {
+        reseed_rng(1);
+        let density = 0.8;
+        for thickness in [0.25, 1.0, 3.0] {
+            let medium = fog(thickness, density);
+            let ray = Ray::new(Point3::new(-1.0, 0.3, -0.2), Vector3::new(1.0, 0.0, 0.0));
+            let passed = transmitted(&medium, &ray, FloatType::INFINITY, 50_000);
+            assert_transmittance(passed, thickness, density);
+        }
+    }
+
+    #[test]
+    fn transmittance_follows_path_length() {
+        reseed_rng(2);
+        let (thickness, density) = (1.0, 0.8);
+        let medium = fog(thickness, density);
+
+        // Oblique and unnormalized, the path through the slab is longer by 1/cos
+        let direction = Vector3::new(3.0, 4.0, 0.0);
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), direction);
+        let path = thickness * direction.length() / direction.x;
+        assert_transmittance(
+            transmitted(&medium, &ray, FloatType::INFINITY, 50_000),
+            path,
+            density,
+        );
+
+        // Starting inside only the rest of the slab is in the way
+        let ray = Ray::new(Point3::new(0.75, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_transmittance(
+            transmitted(&medium, &ray, FloatType::INFINITY, 50_000),
+            0.25,
+            density,
+        );
+
+        // Something opaque in the medium ends the path early
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        assert_transmittance(transmitted(&medium, &ray, 1.5, 50_000), 0.5, density);
+    }
+
+    #[test]
+    fn scatters_inside_the_slab() {
+        reseed_rng(3);
+        let medium = fog(2.0, 0.5);
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(2.0, 0.0, 0.0));
+        for _ in 0..10_000 {
+            if let Some(hit) = medium.hit(&ray, 0.001, FloatType::INFINITY) {
+                assert!((0.0..=2.0).contains(&hit.point.x), "{}", hit.point);
+                assert!((ray.at(hit.t) - hit.point).length() < 1e-5);
+            }
+        }
+        // Rays that miss the slab never scatter
+        let ray = Ray::new(Point3::new(-1.0, 0.0, 0.0), Vector3::new(-1.0, 0.0, 0.0));
+        assert!(medium.hit(&ray, 0.001, FloatType::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::vec::FloatType;
+
+/// Piecewise-constant density over [0,1) proportional to a list of non-negative values
+pub struct Distribution1D {
+    values: Vec<FloatType>,
+    /// Running integral, `cdf[i]` is the probability of sampling below `i / n`
+    cdf: Vec<FloatType>,
+    integral: FloatType,
+}
+
+impl Distribution1D {
+    /// Falls back to a uniform density if all values are zero
+    pub fn new(values: Vec<FloatType>) -> Self {
+        assert!(!values.is_empty(), "Distribution needs at least one value");
+        let n = values.len() as FloatType;
+
+        let mut cdf = Vec::with_capacity(values.len() + 1);
+        cdf.push(0.0);
+        for value in values.iter() {
+            cdf.push(cdf.last().unwrap() + value.max(0.0) / n);
+        }
+        let integral = *cdf.last().unwrap();
+        for (i, entry) in cdf.iter_mut().enumerate().skip(1) {
+            *entry = if integral > 0.0 {
+                *entry / integral
+            } else {
+                i as FloatType / n
+            };
+        }
+
+        Self {
+            values,
+            cdf,
+            integral,
+        }
+    }
+
+    /// Average of the values, the integral of the piecewise-constant function over [0,1)
+    pub fn integral(&self) -> FloatType {
+        self.integral
+    }
+
+    /// Maps a uniform random number in [0,1) to `(x, pdf, index)` with x in [0,1) inside the
+    /// piece `index`
+    pub fn sample(&self, u: FloatType) -> (FloatType, FloatType, usize) {
+        // Last piece whose cdf is <= u
+        let index = self
+            .cdf
+            .partition_point(|&cdf| cdf <= u)
+            .saturating_sub(1)
+            .min(self.values.len() - 1);
+
+        let start = self.cdf[index];
+        let width = self.cdf[index + 1] - start;
+        let offset = if width > 0.0 {
+            (u - start) / width
+        } else {
+            0.0
+        };
+        let x = ((index as FloatType + offset.clamp(0.0, 1.0)) / self.values.len() as FloatType)
+            .min(1.0 - FloatType::EPSILON);
+        (x, self.pdf(index), index)
+    }
+
+    /// Density of the piece `index`
+    pub fn pdf(&self, index: usize) -> FloatType {
+        if self.integral > 0.0 {
+            self.values[index].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+}
+
+/// Piecewise-constant density over [0,1)² from a grid of values, sampled with the marginal
+/// density of the rows and the conditional density within the row
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// Values are given row by row, `v` selects the row and `u` the column
+    pub fn new(values: &[FloatType], width: usize, height: usize) -> Self {
+        assert_eq!(values.len(), width * height, "One value per grid cell");
+        let rows: Vec<Distribution1D> = values
+            .chunks_exact(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Self { rows, marginal }
+    }
+
+    /// Maps two uniform random numbers to `((u, v), pdf)`
+    pub fn sample(&self, u1: FloatType, u2: FloatType) -> ((FloatType, FloatType), FloatType) {
+        let (v, marginal_pdf, row) = self.marginal.sample(u2);
+        let (u, conditional_pdf, _) = self.rows[row].sample(u1);
+        ((u, v), marginal_pdf * conditional_pdf)
+    }
+
+    /// Density at the position (u, v) in [0,1]²
+    pub fn pdf(&self, u: FloatType, v: FloatType) -> FloatType {
+        let row = ((v * self.marginal.len() as FloatType) as usize).min(self.marginal.len() - 1);
+        let columns = self.rows[row].len();
+        let column = ((u * columns as FloatType) as usize).min(columns - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{random_float, reseed_rng};
+
+    #[test]
+    fn samples_follow_values() {
+        reseed_rng(1);
+        let values = vec![1.0, 0.0, 3.0, 4.0];
+        let distribution = Distribution1D::new(values.clone());
+        assert_eq!(distribution.integral(), 2.0);
+
+        let count = 100_000;
+        let mut histogram = [0; 4];
+        for _ in 0..count {
+            let (x, pdf, index) = distribution.sample(random_float());
+            assert_eq!(index, (x * 4.0) as usize);
+            assert_eq!(pdf, distribution.pdf(index));
+            histogram[index] += 1;
+        }
+        for (bucket, value) in histogram.iter().zip(values) {
+            let expected = value / 8.0;
+            assert!((*bucket as FloatType / count as FloatType - expected).abs() < 0.01);
+        }
+        // Density over [0,1), a piece is a quarter wide
+        let total: FloatType = (0..4).map(|i| distribution.pdf(i) / 4.0).sum();
+        assert!((total - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn zero_values_are_uniform() {
+        let distribution = Distribution1D::new(vec![0.0; 5]);
+        assert_eq!(distribution.pdf(3), 1.0);
+        let (x, pdf, index) = distribution.sample(0.5);
+        assert_eq!((index, pdf), (2, 1.0));
+        assert!((x - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sample_is_inside_unit_interval() {
+        let distribution = Distribution1D::new(vec![0.0, 2.0, 0.0]);
+        for u in [0.0, 0.5, 1.0 - FloatType::EPSILON] {
+            let (x, _, index) = distribution.sample(u);
+            assert_eq!(index, 1);
+            assert!((1.0 / 3.0..2.0 / 3.0 + 1e-6).contains(&x), "{}", x);
+        }
+    }
+
+    #[test]
+    fn grid_pdf_matches_samples() {
+        reseed_rng(2);
+        let (width, height) = (3, 2);
+        let values = [1.0, 2.0, 0.0, 0.5, 0.5, 8.0];
+        let distribution = Distribution2D::new(&values, width, height);
+
+        let count = 100_000;
+        let mut histogram = [0; 6];
+        for _ in 0..count {
+            let ((u, v), pdf) = distribution.sample(random_float(), random_float());
+            assert!(
+                (pdf - distribution.pdf(u, v)).abs() < 1e-4,
+                "{} at {}, {}",
+                pdf,
+                u,
+                v
+            );
+            let cell =
+                (v * height as FloatType) as usize * width + (u * width as FloatType) as usize;
+            histogram[cell] += 1;
+        }
+        let total: FloatType = values.iter().sum();
+        for (bucket, value) in histogram.iter().zip(values) {
+            let expected = value / total;
+            assert!((*bucket as FloatType / count as FloatType - expected).abs() < 0.01);
+        }
+        // A cell covers a sixth of the unit square
+        let integral: FloatType = (0..6)
+            .map(|cell| {
+                let u = ((cell % width) as FloatType + 0.5) / width as FloatType;
+                let v = ((cell / width) as FloatType + 0.5) / height as FloatType;
+                distribution.pdf(u, v) / 6.0
+            })
+            .sum();
+        assert!((integral - 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,331 @@
+use crate::{
+    distribution::Distribution2D,
+    image::Framebuffer,
+    sky::Sky,
+    utils::{luminance, random_float},
+    vec::{Color, FloatType, Vector3, PI},
+};
+
+/// Radiance arriving from infinitely far away, seen by rays that leave the scene
+pub enum Environment {
+    /// No light from outside, for closed interiors and studio shots
+    Black,
+    /// Same radiance from every direction
+    Solid(Color),
+    /// Blends from `bottom` straight down to `top` straight up
+    Gradient { bottom: Color, top: Color },
+    /// Latitude-longitude image around the scene
+    Map(EnvironmentMap),
+    /// Analytic daylight with a sun disc
+    Sky(Sky),
+}
+
+impl Environment {
+    /// Radiance arriving along the opposite of `direction`, which doesn't need to be normalized
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        match self {
+            Environment::Black => Color::new_eq(0.0),
+            Environment::Solid(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let t = 0.5 * (direction.unit_vector().y + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Environment::Map(map) => map.radiance(direction),
+            Environment::Sky(sky) => sky.radiance(direction),
+        }
+    }
+
+    /// Picks a direction towards the bright parts, returns it with its radiance and density
+    /// per solid angle. Only images and the sun of the sky are sampled, the others return None.
+    pub fn sample(&self) -> Option<(Vector3, Color, FloatType)> {
+        match self {
+            Environment::Map(map) => map.sample(),
+            Environment::Sky(sky) => sky.sample(),
+            _ => None,
+        }
+    }
+
+    /// Density of `sample` picking the direction, zero if the environment isn't sampled
+    pub fn pdf(&self, direction: &Vector3) -> FloatType {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Default for Environment {
+    /// The white to light blue sky of the book
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: Color::new_eq(1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+/// Equirectangular environment image, e.g. an HDRI light probe
+///
+/// Without rotation the image center is in -z direction and the top row straight up (+y).
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    /// Sine and cosine of the rotation around the y axis
+    rotation: (FloatType, FloatType),
+    intensity: FloatType,
+    /// Density over the image proportional to the brightness of the pixels
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// The rotation turns the image counterclockwise around the y axis (seen from above),
+    /// the intensity scales its radiance.
+    pub fn new(image: Framebuffer, rotation_degrees: FloatType, intensity: FloatType) -> Self {
+        // Rows near the poles cover a smaller solid angle, weight them by it
+        let height = image.height();
+        let weights: Vec<FloatType> = image
+            .rows()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                let (cos_top, cos_bottom) = row_cosines(y, height);
+                let solid_angle = cos_top - cos_bottom;
+                row.iter().map(move |pixel| luminance(pixel) * solid_angle)
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, image.width(), height);
+
+        Self {
+            image,
+            rotation: rotation_degrees.to_radians().sin_cos(),
+            intensity,
+            distribution,
+        }
+    }
+
+    /// Radiance of the pixel the direction points at
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        let (u, v) = direction_to_uv(&self.image_direction(direction).unit_vector());
+        let width = self.image.width();
+        let height = self.image.height();
+        let x = ((u * width as FloatType) as usize).min(width - 1);
+        let y = ((v * height as FloatType) as usize).min(height - 1);
+        self.intensity * self.image.pixels()[y * width + x]
+    }
+
+    /// Picks a direction with a density proportional to the brightness, returns it with
+    /// its radiance and density per solid angle
+    pub fn sample(&self) -> Option<(Vector3, Color, FloatType)> {
+        let ((u, v), uv_pdf) = self.distribution.sample(random_float(), random_float());
+        if uv_pdf <= 0.0 {
+            return None;
+        }
+        // Uniform in cos(theta) within the row, so the density is constant over the solid
+        // angle of the pixel instead of over its image area
+        let height = self.image.height();
+        let row_position = v * height as FloatType;
+        let row = (row_position as usize).min(height - 1);
+        let (cos_top, cos_bottom) = row_cosines(row, height);
+        let cos_theta = cos_top + (row_position - row as FloatType) * (cos_bottom - cos_top);
+        let v = cos_theta.clamp(-1.0, 1.0).acos() / PI;
+
+        let direction = self.world_direction(&uv_to_direction(u, v));
+        let pdf = self.solid_angle_pdf(uv_pdf, row);
+        Some((direction, self.radiance(&direction), pdf))
+    }
+
+    /// Density of `sample` picking the direction, per solid angle
+    pub fn pdf(&self, direction: &Vector3) -> FloatType {
+        let (u, v) = direction_to_uv(&self.image_direction(direction).unit_vector());
+        let height = self.image.height();
+        let row = ((v * height as FloatType) as usize).min(height - 1);
+        self.solid_angle_pdf(self.distribution.pdf(u, v), row)
+    }
+
+    /// Converts the density over the image area into one per solid angle. The image spans
+    /// 2 pi around the y axis, a row spans the difference of its cosines along it.
+    fn solid_angle_pdf(&self, uv_pdf: FloatType, row: usize) -> FloatType {
+        let height = self.image.height();
+        let (cos_top, cos_bottom) = row_cosines(row, height);
+        let row_solid_angle = 2.0 * PI * (cos_top - cos_bottom);
+        if row_solid_angle <= 0.0 {
+            return 0.0;
+        }
+        uv_pdf / (height as FloatType * row_solid_angle)
+    }
+
+    /// Undoes the rotation of the image
+    fn image_direction(&self, direction: &Vector3) -> Vector3 {
+        let (sin, cos) = self.rotation;
+        Vector3::new(
+            cos * direction.x - sin * direction.z,
+            direction.y,
+            sin * direction.x + cos * direction.z,
+        )
+    }
+
+    /// Applies the rotation of the image
+    fn world_direction(&self, direction: &Vector3) -> Vector3 {
+        let (sin, cos) = self.rotation;
+        Vector3::new(
+            cos * direction.x + sin * direction.z,
+            direction.y,
+            -sin * direction.x + cos * direction.z,
+        )
+    }
+}
+
+/// Cosines of the polar angle at the top and bottom edge of the image row
+fn row_cosines(row: usize, height: usize) -> (FloatType, FloatType) {
+    let theta = |y: usize| PI * y as FloatType / height as FloatType;
+    (theta(row).cos(), theta(row + 1).cos())
+}
+
+/// Maps a unit direction onto [0,1]² image coordinates, u around the y axis and v from top
+fn direction_to_uv(direction: &Vector3) -> (FloatType, FloatType) {
+    let phi = direction.x.atan2(-direction.z);
+    let theta = direction.y.clamp(-1.0, 1.0).acos();
+    (0.5 + phi / (2.0 * PI), theta / PI)
+}
+
+/// Inverse of `direction_to_uv`
+fn uv_to_direction(u: FloatType, v: FloatType) -> Vector3 {
+    let (sin_phi, cos_phi) = ((u - 0.5) * 2.0 * PI).sin_cos();
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    Vector3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::reseed_rng;
+
+    /// Dim image with a bright spot off the equator and a dark row near the bottom
+    fn spot_map(rotation_degrees: FloatType) -> EnvironmentMap {
+        let (width, height) = (32, 16);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| match (x, y) {
+                (10..=12, 4..=5) => Color::new(40.0, 30.0, 20.0),
+                (_, 13) => Color::new_eq(0.0),
+                _ => Color::new(0.2 + 0.02 * x as FloatType, 0.3, 0.5),
+            })
+            .collect();
+        EnvironmentMap::new(
+            Framebuffer::new(width, height, pixels),
+            rotation_degrees,
+            2.0,
+        )
+    }
+
+    fn constant_map() -> EnvironmentMap {
+        let pixels = vec![Color::new(0.5, 0.7, 1.0); 24 * 12];
+        EnvironmentMap::new(Framebuffer::new(24, 12, pixels), 0.0, 1.0)
+    }
+
+    fn assert_relative(actual: FloatType, expected: FloatType, tolerance: FloatType) {
+        assert!(
+            (actual - expected).abs() <= tolerance * expected,
+            "{} vs {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn constant_map_is_uniform() {
+        reseed_rng(1);
+        let map = constant_map();
+        let uniform = 1.0 / (4.0 * PI);
+        for _ in 0..1000 {
+            assert_relative(map.pdf(&Vector3::random_unit_vector()), uniform, 1e-3);
+            let (_, _, pdf) = map.sample().unwrap();
+            assert_relative(pdf, uniform, 1e-3);
+        }
+        // Poles included
+        assert_relative(map.pdf(&Vector3::new(0.0, 1.0, 0.0)), uniform, 1e-3);
+        assert_relative(map.pdf(&Vector3::new(0.0, -1.0, 0.0)), uniform, 1e-3);
+    }
+
+    #[test]
+    fn constant_map_samples_are_uniform() {
+        // The fraction above a height is its cap area over the sphere
+        reseed_rng(2);
+        let map = constant_map();
+        let count = 100_000;
+        let above = (0..count)
+            .filter(|_| map.sample().unwrap().0.y > 0.3)
+            .count();
+        let expected = (1.0 - 0.3) / 2.0;
+        assert!((above as FloatType / count as FloatType - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn sample_returns_pdf() {
+        reseed_rng(3);
+        for rotation in [0.0, 75.0] {
+            let map = spot_map(rotation);
+            for _ in 0..1000 {
+                let (direction, radiance, pdf) = map.sample().unwrap();
+                assert_relative(pdf, map.pdf(&direction), 1e-3);
+                let expected = map.radiance(&direction);
+                assert_eq!(
+                    [radiance.x, radiance.y, radiance.z],
+                    [expected.x, expected.y, expected.z]
+                );
+                assert!(radiance.y > 0.0, "Sampled the black row");
+            }
+        }
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        reseed_rng(4);
+        let map = spot_map(30.0);
+        let count = 200_000;
+        let sum: FloatType = (0..count)
+            .map(|_| map.pdf(&Vector3::random_unit_vector()))
+            .sum();
+        let integral = 4.0 * PI * sum / count as FloatType;
+        assert!((integral - 1.0).abs() < 0.03, "{}", integral);
+    }
+
+    #[test]
+    fn samples_follow_pdf() {
+        // Fraction of samples towards the spot against the pdf integrated over the same cone
+        reseed_rng(5);
+        let map = spot_map(0.0);
+        let axis = map.world_direction(&uv_to_direction(11.5 / 32.0, 5.0 / 16.0));
+        let in_cone = |direction: &Vector3| direction.dot(&axis) > 0.9;
+
+        let count = 100_000;
+        let sampled = (0..count)
+            .filter(|_| in_cone(&map.sample().unwrap().0))
+            .count() as FloatType
+            / count as FloatType;
+        let integrated = 4.0
+            * PI
+            * (0..count)
+                .map(|_| Vector3::random_unit_vector())
+                .filter(in_cone)
+                .map(|direction| map.pdf(&direction))
+                .sum::<FloatType>()
+            / count as FloatType;
+        assert!(sampled > 0.5, "{}", sampled);
+        assert!(
+            (sampled - integrated).abs() < 0.03,
+            "{} vs {}",
+            sampled,
+            integrated
+        );
+    }
+
+    #[test]
+    fn environment_forwards_to_map() {
+        reseed_rng(6);
+        let environment = Environment::Map(spot_map(0.0));
+        let (direction, _, pdf) = environment.sample().unwrap();
+        assert_relative(pdf, environment.pdf(&direction), 1e-3);
+        assert!(Environment::Solid(Color::new_eq(1.0)).sample().is_none());
+        assert_eq!(Environment::Black.pdf(&direction), 0.0);
+    }
+}
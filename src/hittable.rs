@@ -1,9 +1,9 @@
-use std::ops::RangeInclusive;
-
 use crate::{
+    aabb::Aabb,
     material::Material,
+    mesh::TriangleMesh,
     ray::Ray,
-    vec::{FloatType, Point3, Vector3},
+    vec::{Color, FloatType, Point3, Vector3},
 };
 
 #[derive(Clone)]
@@ -12,7 +12,18 @@ pub struct HitRecord<'a> {
     pub normal: Vector3,
     pub material: &'a Material,
     pub t: FloatType,
+    /// Surface (texture) coordinates of the hit point
+    pub u: FloatType,
+    pub v: FloatType,
+    /// Derivatives of the hit point along the texture coordinates, zero if the surface
+    /// doesn't provide them. Orient normal and bump maps.
+    pub dpdu: Vector3,
+    pub dpdv: Vector3,
+    /// Interpolated vertex color, if the object has per-vertex colors
+    pub color: Option<Color>,
     pub facing_front: bool,
+    /// Index of the hit object in `Lights`, if it's sampled as light
+    pub light: Option<usize>,
 }
 
 impl<'a> HitRecord<'a> {
@@ -21,13 +32,20 @@ impl<'a> HitRecord<'a> {
         t: FloatType,
         r: &Ray,
         outward_normal: Vector3,
+        (u, v): (FloatType, FloatType),
         material: &'a Material,
     ) -> HitRecord<'a> {
         let facing_front = r.direction.dot(&outward_normal) < 0.0;
         HitRecord {
             point,
             t,
+            u,
+            v,
+            dpdu: Vector3::new_eq(0.0),
+            dpdv: Vector3::new_eq(0.0),
+            color: None,
             facing_front,
+            light: None,
             normal: match facing_front {
                 true => outward_normal,
                 false => -outward_normal,
@@ -35,6 +53,28 @@ impl<'a> HitRecord<'a> {
             material,
         }
     }
+
+    /// Replaces the normal used for shading (e.g. an interpolated vertex normal)
+    ///
+    /// The new normal is flipped onto the same side as the geometric one.
+    pub fn set_shading_normal(&mut self, shading_normal: Vector3) {
+        self.normal = if shading_normal.dot(&self.normal) < 0.0 {
+            -shading_normal
+        } else {
+            shading_normal
+        };
+    }
+
+    /// Applies the normal and bump maps of the material to the shading normal and replaces
+    /// the material by the mapped one
+    pub fn apply_normal_map(mut self) -> Self {
+        while let Material::NormalMapped { base, map } = self.material {
+            let normal = map.perturb(&self);
+            self.set_shading_normal(normal);
+            self.material = base;
+        }
+        self
+    }
 }
 
 #[derive(Default)]
@@ -43,6 +83,7 @@ pub struct HittableList {
 }
 
 impl HittableList {
+    #[allow(unused)]
     pub fn clear(&mut self) {
         self.objects.clear()
     }
@@ -50,10 +91,17 @@ impl HittableList {
     pub fn add(&mut self, object: Box<dyn Hittable>) {
         self.objects.push(object)
     }
+
+    /// Adds every triangle of the mesh as its own object
+    pub fn add_mesh(&mut self, mesh: TriangleMesh) {
+        for triangle in mesh.into_triangles() {
+            self.add(Box::new(triangle));
+        }
+    }
 }
 
 impl Hittable for HittableList {
-    fn hit(&self, r: &crate::ray::Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
         let mut anything_hit = None;
         let mut closest_so_far = t_max;
 
@@ -66,8 +114,36 @@ impl Hittable for HittableList {
 
         anything_hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .map(|object| object.bounding_box())
+            .reduce(|acc, bbox| match (acc, bbox) {
+                (Some(acc), Some(bbox)) => Some(acc.surrounding_box(&bbox)),
+                _ => None,
+            })
+            .flatten()
+    }
 }
 
 pub trait Hittable: Sync + Send {
-    fn hit(&self, r: &crate::ray::Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>>;
+
+    /// Box enclosing the object, or None if it is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// The object as light if it has an emissive material and should be sampled explicitly
+    fn as_light(&self) -> Option<&dyn AreaLight> {
+        None
+    }
+}
+
+/// Emissive object that can be sampled from the points it illuminates
+pub trait AreaLight {
+    /// Density of `random` picking the direction from `origin`, per solid angle
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> FloatType;
+
+    /// Random direction from `origin` towards the object
+    fn random(&self, origin: &Point3) -> Vector3;
 }
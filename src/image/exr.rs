@@ -0,0 +1,550 @@
+//! Scanline OpenEXR writer for single part images
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+
+use super::Framebuffer;
+
+const MAGIC: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+
+/// Version 2, single part scanline image with short names
+const VERSION: [u8; 4] = [2, 0, 0, 0];
+
+/// Runs shorter than this are stored as literals by the RLE compression
+const MIN_RUN_LENGTH: usize = 3;
+const MAX_RUN_LENGTH: usize = 127;
+
+/// Storage of the channel samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrPixelType {
+    /// 16 bit IEEE 754 half precision float
+    #[default]
+    Half,
+    /// 32 bit IEEE 754 single precision float
+    Float,
+}
+
+impl ExrPixelType {
+    fn id(self) -> i32 {
+        match self {
+            ExrPixelType::Half => 1,
+            ExrPixelType::Float => 2,
+        }
+    }
+}
+
+/// Lossless compression methods of the scanline blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExrCompression {
+    None,
+    /// Run length encoding, fast but only good on flat areas
+    Rle,
+    /// Deflate of single scanlines
+    Zips,
+    /// Deflate of blocks of 16 scanlines
+    #[default]
+    Zip,
+}
+
+impl ExrCompression {
+    fn id(self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Rle => 1,
+            ExrCompression::Zips => 2,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(self) -> usize {
+        match self {
+            ExrCompression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+/// Writes the RGB pixels and all extra channels of the framebuffer
+// FloatType may be f64, the samples are stored as f32 at most
+#[allow(clippy::unnecessary_cast)]
+pub fn write_exr(
+    out: &mut impl Write,
+    framebuffer: &Framebuffer,
+    compression: ExrCompression,
+    pixel_type: ExrPixelType,
+) -> io::Result<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    let out_of_range = |value: usize| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("EXR dimensions must be in 1..2^31, got {}", value),
+        )
+    };
+    let max_x = i32::try_from(width).map_err(|_| out_of_range(width))? - 1;
+    let max_y = i32::try_from(height).map_err(|_| out_of_range(height))? - 1;
+    if max_x < 0 || max_y < 0 {
+        return Err(out_of_range(width.min(height)));
+    }
+
+    // Readers expect the channels sorted by name, in the header as well as in the data
+    let pixels = framebuffer.pixels();
+    let mut channels: Vec<(&str, Vec<f32>)> = vec![
+        ("R", pixels.iter().map(|pixel| pixel.r() as f32).collect()),
+        ("G", pixels.iter().map(|pixel| pixel.g() as f32).collect()),
+        ("B", pixels.iter().map(|pixel| pixel.b() as f32).collect()),
+    ];
+    for channel in framebuffer.channels() {
+        let values = channel.values.iter().map(|&value| value as f32).collect();
+        channels.push((&channel.name, values));
+    }
+    channels.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC);
+    header.extend_from_slice(&VERSION);
+    let mut channel_list = Vec::new();
+    for (name, _) in &channels {
+        channel_list.extend_from_slice(name.as_bytes());
+        channel_list.push(0);
+        channel_list.extend_from_slice(&pixel_type.id().to_le_bytes());
+        // pLinear and reserved bytes, then x and y sampling
+        channel_list.extend_from_slice(&[0; 4]);
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+        channel_list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channel_list.push(0);
+    let window: Vec<u8> = [0, 0, max_x, max_y]
+        .iter()
+        .flat_map(|value| value.to_le_bytes())
+        .collect();
+    write_attribute(&mut header, "channels", "chlist", &channel_list);
+    write_attribute(
+        &mut header,
+        "compression",
+        "compression",
+        &[compression.id()],
+    );
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    // Increasing y
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let lines_per_block = compression.lines_per_block();
+    let blocks: Vec<Vec<u8>> = (0..height)
+        .step_by(lines_per_block)
+        .map(|first_line| {
+            let lines = first_line..(first_line + lines_per_block).min(height);
+            let mut raw = Vec::new();
+            for y in lines {
+                for (_, values) in &channels {
+                    for &value in &values[y * width..(y + 1) * width] {
+                        match pixel_type {
+                            ExrPixelType::Half => {
+                                raw.extend_from_slice(&f32_to_half(value).to_le_bytes())
+                            }
+                            ExrPixelType::Float => raw.extend_from_slice(&value.to_le_bytes()),
+                        }
+                    }
+                }
+            }
+            compress(raw, compression)
+        })
+        .collect();
+
+    // Offset table with the file position of every block, each block is prefixed by its
+    // first line and data size
+    out.write_all(&header)?;
+    let mut offset = (header.len() + 8 * blocks.len()) as u64;
+    for block in &blocks {
+        out.write_all(&offset.to_le_bytes())?;
+        offset += 8 + block.len() as u64;
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        out.write_all(&((index * lines_per_block) as i32).to_le_bytes())?;
+        out.write_all(&(block.len() as i32).to_le_bytes())?;
+        out.write_all(block)?;
+    }
+    Ok(())
+}
+
+fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Compresses a block, readers take data that isn't smaller than the raw block as raw
+fn compress(raw: Vec<u8>, compression: ExrCompression) -> Vec<u8> {
+    let compressed = match compression {
+        ExrCompression::None => return raw,
+        ExrCompression::Rle => run_length_encode(&predict(&raw)),
+        ExrCompression::Zips | ExrCompression::Zip => compress_to_vec_zlib(&predict(&raw), 6),
+    };
+    if compressed.len() < raw.len() {
+        compressed
+    } else {
+        raw
+    }
+}
+
+/// Splits the bytes into even and odd positions, then stores byte differences. This turns
+/// the smoothly varying floats into many similar bytes that compress well.
+fn predict(raw: &[u8]) -> Vec<u8> {
+    let mut reordered: Vec<u8> = raw.iter().step_by(2).copied().collect();
+    reordered.extend(raw.iter().skip(1).step_by(2));
+
+    let mut previous = reordered.first().copied().unwrap_or(0);
+    for byte in reordered.iter_mut().skip(1) {
+        let current = *byte;
+        *byte = current.wrapping_sub(previous).wrapping_add(128);
+        previous = current;
+    }
+    reordered
+}
+
+/// Encodes runs as `length - 1, value` and literals as `-length, values...`
+fn run_length_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len());
+    let mut start = 0;
+    while start < data.len() {
+        let run_length = data[start..]
+            .iter()
+            .take(MAX_RUN_LENGTH + 1)
+            .take_while(|&&value| value == data[start])
+            .count();
+        if run_length >= MIN_RUN_LENGTH {
+            encoded.push((run_length - 1) as u8);
+            encoded.push(data[start]);
+            start += run_length;
+            continue;
+        }
+
+        // Literals up to the next run of three equal bytes
+        let mut end = start + 1;
+        while end < data.len()
+            && end - start < MAX_RUN_LENGTH
+            && !(end + 2 < data.len() && data[end] == data[end + 1] && data[end] == data[end + 2])
+        {
+            end += 1;
+        }
+        encoded.push((-((end - start) as i32)) as u8);
+        encoded.extend_from_slice(&data[start..end]);
+        start = end;
+    }
+    encoded
+}
+
+/// Rounds to the nearest half precision float, ties to even
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    // Infinity and NaN, NaN keeps a set mantissa bit
+    if exponent == 0xFF {
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1F {
+        return sign | 0x7C00;
+    }
+    let (mantissa, shift) = if half_exponent <= 0 {
+        // Subnormal half, the implicit leading one becomes explicit
+        if half_exponent < -10 {
+            return sign;
+        }
+        (mantissa | 0x80_0000, (14 - half_exponent) as u32)
+    } else {
+        (mantissa, 13)
+    };
+
+    let mut half = mantissa >> shift;
+    if half_exponent > 0 {
+        half |= (half_exponent as u32) << 10;
+    }
+    let remainder = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    // Carries into the exponent, which rounds up to the next binade or infinity
+    if remainder > halfway || (remainder == halfway && half & 1 == 1) {
+        half += 1;
+    }
+    sign | half as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use miniz_oxide::inflate::decompress_to_vec_zlib;
+
+    use super::*;
+    use crate::{image::test_framebuffer, vec::FloatType};
+
+    fn half_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = ((half >> 10) & 0x1F) as i32;
+        let mantissa = (half & 0x3FF) as f32;
+        match exponent {
+            0 => sign * mantissa * 2f32.powi(-24),
+            0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1F => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    fn run_length_decode(data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let count = data[position] as i8;
+            if count < 0 {
+                let length = -(count as i32) as usize;
+                decoded.extend_from_slice(&data[position + 1..position + 1 + length]);
+                position += 1 + length;
+            } else {
+                decoded.resize(decoded.len() + count as usize + 1, data[position + 1]);
+                position += 2;
+            }
+        }
+        decoded
+    }
+
+    /// Reverses `predict`
+    fn unpredict(mut data: Vec<u8>) -> Vec<u8> {
+        for index in 1..data.len() {
+            data[index] = data[index - 1].wrapping_add(data[index]).wrapping_sub(128);
+        }
+        let (even, odd) = data.split_at(data.len().div_ceil(2));
+        let mut raw = Vec::with_capacity(data.len());
+        for (index, &byte) in even.iter().enumerate() {
+            raw.push(byte);
+            if let Some(&byte) = odd.get(index) {
+                raw.push(byte);
+            }
+        }
+        raw
+    }
+
+    /// Reads the files `write_exr` writes, returns the channels with their values
+    fn read_exr(bytes: &[u8]) -> (usize, usize, Vec<(String, Vec<f32>)>) {
+        let i32_at =
+            |position: usize| i32::from_le_bytes(bytes[position..position + 4].try_into().unwrap());
+        let string_at = |position: usize| {
+            let length = bytes[position..]
+                .iter()
+                .position(|&byte| byte == 0)
+                .unwrap();
+            String::from_utf8(bytes[position..position + length].to_vec()).unwrap()
+        };
+        assert_eq!(bytes[..4], MAGIC);
+        assert_eq!(bytes[4..8], VERSION);
+
+        let mut position = 8;
+        let mut channels = Vec::new();
+        let mut compression = None;
+        let mut size = None;
+        while bytes[position] != 0 {
+            let name = string_at(position);
+            position += name.len() + 1;
+            let kind = string_at(position);
+            position += kind.len() + 1;
+            let length = i32_at(position) as usize;
+            position += 4;
+            let value = position..position + length;
+            match name.as_str() {
+                "channels" => {
+                    let mut channel = value.start;
+                    while bytes[channel] != 0 {
+                        let name = string_at(channel);
+                        channel += name.len() + 1;
+                        channels.push((name, i32_at(channel)));
+                        channel += 16;
+                    }
+                }
+                "compression" => compression = Some(bytes[value.start]),
+                "dataWindow" => {
+                    let max_x = i32_at(value.start + 8) as usize;
+                    let max_y = i32_at(value.start + 12) as usize;
+                    size = Some((max_x + 1, max_y + 1));
+                }
+                _ => {}
+            }
+            position = value.end;
+        }
+        position += 1;
+
+        let (width, height) = size.unwrap();
+        let lines_per_block = if compression == Some(3) { 16 } else { 1 };
+        let mut values = vec![Vec::new(); channels.len()];
+        for block in 0..height.div_ceil(lines_per_block) {
+            let offset = u64::from_le_bytes(
+                bytes[position + 8 * block..position + 8 * block + 8]
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let first_line = i32_at(offset) as usize;
+            assert_eq!(first_line, block * lines_per_block);
+            let lines = lines_per_block.min(height - first_line);
+            let data = &bytes[offset + 8..offset + 8 + i32_at(offset + 4) as usize];
+            let sample_size = |pixel_type: i32| if pixel_type == 1 { 2 } else { 4 };
+            let raw_size: usize = channels
+                .iter()
+                .map(|(_, pixel_type)| lines * width * sample_size(*pixel_type))
+                .sum();
+            let raw = if data.len() == raw_size {
+                data.to_vec()
+            } else if compression == Some(1) {
+                unpredict(run_length_decode(data))
+            } else {
+                unpredict(decompress_to_vec_zlib(data).unwrap())
+            };
+            assert_eq!(raw.len(), raw_size);
+
+            let mut samples = raw.as_slice();
+            for _ in 0..lines {
+                for ((_, pixel_type), values) in channels.iter().zip(&mut values) {
+                    for _ in 0..width {
+                        let (sample, rest) = samples.split_at(sample_size(*pixel_type));
+                        values.push(match sample {
+                            [low, high] => half_to_f32(u16::from_le_bytes([*low, *high])),
+                            _ => f32::from_le_bytes(sample.try_into().unwrap()),
+                        });
+                        samples = rest;
+                    }
+                }
+            }
+        }
+        let names = channels.into_iter().map(|(name, _)| name);
+        (width, height, names.zip(values).collect())
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    /// Returns the file size
+    fn round_trip(compression: ExrCompression, pixel_type: ExrPixelType) -> usize {
+        let (width, height) = (21, 19);
+        let mut framebuffer = test_framebuffer(width, height);
+        let depth: Vec<FloatType> = (0..width * height).map(|i| i as FloatType).collect();
+        framebuffer.add_channel("Z", depth.clone());
+        framebuffer.add_channel("A", vec![1.0; width * height]);
+        let mut bytes = Vec::new();
+        write_exr(&mut bytes, &framebuffer, compression, pixel_type).unwrap();
+
+        let (read_width, read_height, channels) = read_exr(&bytes);
+        assert_eq!((read_width, read_height), (width, height));
+        let names: Vec<&str> = channels.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["A", "B", "G", "R", "Z"]);
+
+        let pixels = framebuffer.pixels();
+        let expected = [
+            vec![1.0; width * height],
+            pixels.iter().map(|pixel| pixel.b()).collect(),
+            pixels.iter().map(|pixel| pixel.g()).collect(),
+            pixels.iter().map(|pixel| pixel.r()).collect(),
+            depth,
+        ];
+        for ((name, actual), expected) in channels.iter().zip(&expected) {
+            for (&actual, &expected) in actual.iter().zip(expected) {
+                let expected = expected as f32;
+                let tolerance = match pixel_type {
+                    // 11 significant bits
+                    ExrPixelType::Half => expected.abs() / 2048.0,
+                    ExrPixelType::Float => 0.0,
+                };
+                assert!(
+                    (actual - expected).abs() <= tolerance,
+                    "{} of channel {} read back as {}",
+                    expected,
+                    name,
+                    actual
+                );
+            }
+        }
+        bytes.len()
+    }
+
+    #[test]
+    fn round_trip_all_compressions() {
+        for pixel_type in [ExrPixelType::Half, ExrPixelType::Float] {
+            let uncompressed = round_trip(ExrCompression::None, pixel_type);
+            for compression in [
+                ExrCompression::Rle,
+                ExrCompression::Zips,
+                ExrCompression::Zip,
+            ] {
+                // Compressed blocks are decoded, not only raw fallbacks
+                assert!(round_trip(compression, pixel_type) < uncompressed);
+            }
+        }
+    }
+
+    #[test]
+    fn half_of_normal_values() {
+        assert_eq!(f32_to_half(0.0), 0x0000);
+        assert_eq!(f32_to_half(-0.0), 0x8000);
+        assert_eq!(f32_to_half(1.0), 0x3C00);
+        assert_eq!(f32_to_half(-2.0), 0xC000);
+        assert_eq!(f32_to_half(0.1), 0x2E66);
+        assert_eq!(f32_to_half(65504.0), 0x7BFF);
+        // Ties to even
+        assert_eq!(f32_to_half(1.0 + 2f32.powi(-11)), 0x3C00);
+        assert_eq!(f32_to_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3C02);
+        // Rounding up carries into the exponent
+        assert_eq!(f32_to_half(2.0 - 2f32.powi(-12)), 0x4000);
+    }
+
+    #[test]
+    fn half_of_denormals() {
+        assert_eq!(f32_to_half(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_half(2f32.powi(-14) - 2f32.powi(-24)), 0x03FF);
+        assert_eq!(f32_to_half(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_half(-2f32.powi(-24)), 0x8001);
+        // Halfway to the smallest denormal rounds to even, anything above rounds up
+        assert_eq!(f32_to_half(2f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_half(1.5 * 2f32.powi(-25)), 0x0001);
+        assert_eq!(f32_to_half(2f32.powi(-30)), 0x0000);
+        assert_eq!(f32_to_half(f32::MIN_POSITIVE), 0x0000);
+        // The largest denormal rounds up to the smallest normal
+        assert_eq!(f32_to_half(2f32.powi(-14) - 2f32.powi(-26)), 0x0400);
+    }
+
+    #[test]
+    fn half_overflows_to_infinity() {
+        assert_eq!(f32_to_half(65520.0), 0x7C00);
+        assert_eq!(f32_to_half(1.0e6), 0x7C00);
+        assert_eq!(f32_to_half(-1.0e6), 0xFC00);
+        assert_eq!(f32_to_half(f32::MAX), 0x7C00);
+        assert_eq!(f32_to_half(f32::INFINITY), 0x7C00);
+        assert_eq!(f32_to_half(f32::NEG_INFINITY), 0xFC00);
+    }
+
+    #[test]
+    fn half_of_nan_stays_nan() {
+        for nan in [f32::NAN, -f32::NAN, f32::from_bits(0x7F80_0001)] {
+            let half = f32_to_half(nan);
+            assert_eq!(half & 0x7C00, 0x7C00);
+            assert_ne!(half & 0x03FF, 0);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::slice::ChunksExact;
+
+use crate::vec::{Color, FloatType};
+
+/// Additional per pixel values of a render, like depth or normals
+pub struct Channel {
+    pub name: String,
+    pub values: Vec<FloatType>,
+}
+
+/// Linear radiance of every pixel of a render, top row first
+///
+/// Extra named channels are only written by the formats that support them.
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    channels: Vec<Channel>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "One pixel per image position");
+        Self {
+            width,
+            height,
+            pixels,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// Pixel rows from top to bottom
+    pub fn rows(&self) -> ChunksExact<'_, Color> {
+        self.pixels.chunks_exact(self.width)
+    }
+
+    /// Adds a named channel with one value per pixel, top row first
+    ///
+    /// Panics if the name is empty, taken or one of the color channels R, G and B.
+    pub fn add_channel(&mut self, name: impl Into<String>, values: Vec<FloatType>) {
+        let name = name.into();
+        assert!(
+            !name.is_empty() && !["R", "G", "B"].contains(&name.as_str()),
+            "Invalid channel name '{}'",
+            name
+        );
+        assert!(
+            self.channels.iter().all(|channel| channel.name != name),
+            "Channel '{}' was already added",
+            name
+        );
+        assert_eq!(
+            values.len(),
+            self.pixels.len(),
+            "One value per pixel in channel '{}'",
+            name
+        );
+        self.channels.push(Channel { name, values });
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+}
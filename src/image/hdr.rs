@@ -0,0 +1,291 @@
+//! Radiance RGBE (.hdr) writer
+use std::io::{self, Write};
+
+use super::Framebuffer;
+use crate::vec::{Color, FloatType};
+
+/// Run length encoding is only defined for scanlines of this width range
+const RLE_WIDTHS: std::ops::Range<usize> = 8..0x8000;
+
+/// Shortest run that is worth encoding as run
+const MIN_RUN_LENGTH: usize = 4;
+
+/// Longest run or literal a single count byte can describe
+const MAX_RUN_LENGTH: usize = 127;
+const MAX_LITERAL_LENGTH: usize = 128;
+
+/// Writes the linear pixels as Radiance RGBE, run length encoded where possible
+pub fn write_hdr(out: &mut impl Write, framebuffer: &Framebuffer) -> io::Result<()> {
+    let width = framebuffer.width();
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        framebuffer.height(),
+        width
+    )?;
+
+    let mut component = Vec::with_capacity(width);
+    for row in framebuffer.rows() {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        if !RLE_WIDTHS.contains(&width) {
+            out.write_all(&rgbe.concat())?;
+            continue;
+        }
+
+        // Scanline header, then each component run length encoded on its own
+        out.write_all(&[2, 2, (width >> 8) as u8, (width & 0xFF) as u8])?;
+        for index in 0..4 {
+            component.clear();
+            component.extend(rgbe.iter().map(|pixel| pixel[index]));
+            write_rle(out, &component)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shared exponent encoding, negative and NaN components become zero
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let [r, g, b] = [color.r(), color.g(), color.b()].map(|value| value.max(0.0));
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0; 4];
+    }
+    if !max.is_finite() {
+        return [255, 255, 255, 255];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / (2.0 as FloatType).powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / (2.0 as FloatType).powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+/// Encodes runs as `128 + length, value` and everything else as `length, values...`
+fn write_rle(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    let mut current = 0;
+    while current < data.len() {
+        // Find the start of the next run that is long enough
+        let mut run_start = current;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = data[run_start..]
+                .iter()
+                .take(MAX_RUN_LENGTH)
+                .take_while(|&&value| value == data[run_start])
+                .count();
+            if run_length >= MIN_RUN_LENGTH {
+                break;
+            }
+            run_start += run_length;
+        }
+
+        // Literal bytes up to the run
+        while current < run_start {
+            let length = (run_start - current).min(MAX_LITERAL_LENGTH);
+            out.write_all(&[length as u8])?;
+            out.write_all(&data[current..current + length])?;
+            current += length;
+        }
+
+        if run_length >= MIN_RUN_LENGTH {
+            out.write_all(&[(128 + run_length) as u8, data[run_start]])?;
+            current += run_length;
+        }
+    }
+    Ok(())
+}
+
+/// Reads a Radiance RGBE image with flat, old style or adaptive run length encoded scanlines
+pub fn read_hdr(bytes: &[u8]) -> io::Result<Framebuffer> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    // Header lines up to an empty line, then the resolution line
+    let mut position = 0;
+    let mut next_line = || {
+        let start = position;
+        let length = bytes[start..].iter().position(|&byte| byte == b'\n')?;
+        position = start + length + 1;
+        Some(String::from_utf8_lossy(&bytes[start..start + length]).into_owned())
+    };
+    let magic = next_line().unwrap_or_default();
+    if !magic.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file".to_string()));
+    }
+    loop {
+        let line = next_line().ok_or_else(|| invalid("truncated header".to_string()))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid(format!("unsupported pixel format '{}'", format)));
+            }
+        }
+    }
+    let resolution = next_line().ok_or_else(|| invalid("missing resolution".to_string()))?;
+    let (flip, height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        [y_axis @ ("-Y" | "+Y"), height, "+X", width] => match (height.parse(), width.parse()) {
+            (Ok(height), Ok(width)) if height > 0 && width > 0 => (y_axis == "+Y", height, width),
+            _ => return Err(invalid(format!("invalid resolution '{}'", resolution))),
+        },
+        _ => {
+            return Err(invalid(format!(
+                "unsupported orientation '{}', expected -Y <height> +X <width>",
+                resolution
+            )))
+        }
+    };
+
+    let mut data = &bytes[position..];
+    let mut rows: Vec<Vec<Color>> = Vec::with_capacity(height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        data = read_scanline(data, &mut scanline)
+            .ok_or_else(|| invalid(format!("truncated or corrupt scanline {}", rows.len())))?;
+        rows.push(scanline.iter().map(from_rgbe).collect());
+    }
+    // +Y stores the bottom row first
+    if flip {
+        rows.reverse();
+    }
+    Ok(Framebuffer::new(width, height, rows.concat()))
+}
+
+/// Decodes one scanline, returns the remaining data
+fn read_scanline<'a>(mut data: &'a [u8], scanline: &mut [[u8; 4]]) -> Option<&'a [u8]> {
+    let width = scanline.len();
+    let adaptive = RLE_WIDTHS.contains(&width)
+        && data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && data[2] & 0x80 == 0
+        && ((data[2] as usize) << 8 | data[3] as usize) == width;
+
+    if adaptive {
+        data = &data[4..];
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let (&count, rest) = data.split_first()?;
+                if count > 128 {
+                    let length = count as usize - 128;
+                    let (&value, rest) = rest.split_first()?;
+                    for pixel in scanline.get_mut(x..x + length)? {
+                        pixel[component] = value;
+                    }
+                    x += length;
+                    data = rest;
+                } else {
+                    let length = count as usize;
+                    if length == 0 || rest.len() < length {
+                        return None;
+                    }
+                    for (pixel, &value) in scanline.get_mut(x..x + length)?.iter_mut().zip(rest) {
+                        pixel[component] = value;
+                    }
+                    x += length;
+                    data = &rest[length..];
+                }
+            }
+        }
+        return Some(data);
+    }
+
+    // Flat pixels, where (1, 1, 1, n) repeats the previous pixel with growing shifts
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let pixel = [*data.first()?, *data.get(1)?, *data.get(2)?, *data.get(3)?];
+        data = &data[4..];
+        if pixel[..3] == [1, 1, 1] && x > 0 {
+            let count = (pixel[3] as usize) << shift;
+            let previous = scanline[x - 1];
+            for repeated in scanline.get_mut(x..x + count)? {
+                *repeated = previous;
+            }
+            x += count;
+            shift += 8;
+        } else {
+            scanline[x] = pixel;
+            x += 1;
+            shift = 0;
+        }
+    }
+    Some(data)
+}
+
+fn from_rgbe(pixel: &[u8; 4]) -> Color {
+    if pixel[3] == 0 {
+        return Color::new_eq(0.0);
+    }
+    // Center of the quantization interval
+    let scale = (2.0 as FloatType).powi(pixel[3] as i32 - (128 + 8));
+    Color::new(
+        (pixel[0] as FloatType + 0.5) * scale,
+        (pixel[1] as FloatType + 0.5) * scale,
+        (pixel[2] as FloatType + 0.5) * scale,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::test_framebuffer;
+
+    fn round_trip(width: usize, height: usize) {
+        let framebuffer = test_framebuffer(width, height);
+        let mut bytes = Vec::new();
+        write_hdr(&mut bytes, &framebuffer).unwrap();
+
+        let read = read_hdr(&bytes).unwrap();
+        assert_eq!((read.width(), read.height()), (width, height));
+        for (expected, actual) in framebuffer.pixels().iter().zip(read.pixels()) {
+            // The shared exponent leaves 8 bits of mantissa to the largest component
+            let tolerance = expected.r().max(expected.g()).max(expected.b()) / 128.0;
+            for (expected, actual) in [
+                (expected.r(), actual.r()),
+                (expected.g(), actual.g()),
+                (expected.b(), actual.b()),
+            ] {
+                assert!(
+                    (expected - actual).abs() <= tolerance,
+                    "{} read back as {}",
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_flat() {
+        round_trip(5, 3);
+    }
+
+    #[test]
+    fn round_trip_run_length_encoded() {
+        round_trip(300, 4);
+    }
+
+    #[test]
+    fn rgbe_of_special_values() {
+        assert_eq!(to_rgbe(&Color::new_eq(0.0)), [0; 4]);
+        assert_eq!(to_rgbe(&Color::new(-1.0, FloatType::NAN, 0.0)), [0; 4]);
+        assert_eq!(to_rgbe(&Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(
+            to_rgbe(&Color::new(FloatType::INFINITY, 0.0, 0.0)),
+            [255; 4]
+        );
+        // Decoded to the center of the quantization interval
+        assert_eq!(from_rgbe(&[128, 64, 0, 129]).r(), 128.5 / 128.0);
+    }
+}
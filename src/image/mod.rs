@@ -0,0 +1,189 @@
+//! Writers for the rendered image and readers for textures and environment maps
+mod exr;
+mod framebuffer;
+mod hdr;
+mod pfm;
+mod png;
+mod ppm;
+
+pub use exr::{ExrCompression, ExrPixelType};
+pub use framebuffer::Framebuffer;
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    utils::{linear_to_srgb, srgb_to_linear},
+    vec::{Color, FloatType},
+};
+
+/// File formats the renderer can write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFormat {
+    #[default]
+    Ppm,
+    Png,
+    /// Radiance RGBE
+    Hdr,
+    /// Portable FloatMap
+    Pfm,
+    /// OpenEXR, the only format with extra channels
+    Exr,
+}
+
+impl ImageFormat {
+    /// Picks the format from the (case insensitive) file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::Hdr),
+            "pfm" => Some(ImageFormat::Pfm),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+/// Bits per channel of the integer formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn max_value(self) -> u16 {
+        match self {
+            BitDepth::Eight => u8::MAX as u16,
+            BitDepth::Sixteen => u16::MAX,
+        }
+    }
+}
+
+/// Format specific settings, each format only looks at its own
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodeOptions {
+    /// PNG and PPM
+    pub bit_depth: BitDepth,
+    pub exr_compression: ExrCompression,
+    pub exr_pixel_type: ExrPixelType,
+}
+
+/// Writes the framebuffer in the given format
+///
+/// The integer formats are sRGB encoded, the floating point formats keep the linear
+/// radiance.
+pub fn write_image(
+    out: &mut impl Write,
+    format: ImageFormat,
+    options: &EncodeOptions,
+    framebuffer: &Framebuffer,
+) -> io::Result<()> {
+    let (width, height) = (framebuffer.width(), framebuffer.height());
+    match format {
+        ImageFormat::Ppm => {
+            let samples = quantize(framebuffer.pixels(), options.bit_depth);
+            ppm::write_ppm(out, width, height, options.bit_depth, &samples)?
+        }
+        ImageFormat::Png => {
+            let samples = quantize(framebuffer.pixels(), options.bit_depth);
+            png::write_png(out, width, height, options.bit_depth, &samples)?
+        }
+        ImageFormat::Hdr => hdr::write_hdr(out, framebuffer)?,
+        ImageFormat::Pfm => pfm::write_pfm(out, framebuffer)?,
+        ImageFormat::Exr => exr::write_exr(
+            out,
+            framebuffer,
+            options.exr_compression,
+            options.exr_pixel_type,
+        )?,
+    }
+    out.flush()
+}
+
+/// Reads a linear image, the format is picked by the file extension
+///
+/// Supported are Radiance RGBE (.hdr), Portable FloatMaps (.pfm), PNG and PPM. The integer
+/// formats are decoded from sRGB.
+pub fn read_image(path: &Path) -> io::Result<Framebuffer> {
+    read(path, true)
+}
+
+/// Reads an image of non-color data like normal or height maps, integer formats are scaled
+/// to [0,1] without sRGB decoding
+pub fn read_image_data(path: &Path) -> io::Result<Framebuffer> {
+    read(path, false)
+}
+
+fn read(path: &Path, decode_srgb: bool) -> io::Result<Framebuffer> {
+    let with_path =
+        |error: io::Error| io::Error::new(error.kind(), format!("{}: {}", path.display(), error));
+    let bytes = fs::read(path).map_err(with_path)?;
+    let decode = |framebuffer| {
+        if decode_srgb {
+            srgb_to_linear_pixels(framebuffer)
+        } else {
+            framebuffer
+        }
+    };
+    match ImageFormat::from_path(path) {
+        Some(ImageFormat::Hdr) => hdr::read_hdr(&bytes),
+        Some(ImageFormat::Pfm) => pfm::read_pfm(&bytes),
+        Some(ImageFormat::Png) => png::read_png(&bytes).map(decode),
+        Some(ImageFormat::Ppm) => ppm::read_ppm(&bytes).map(decode),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported image format, expected a .hdr, .pfm, .png or .ppm file",
+        )),
+    }
+    .map_err(with_path)
+}
+
+/// Converts sRGB encoded pixels to linear
+fn srgb_to_linear_pixels(framebuffer: Framebuffer) -> Framebuffer {
+    let pixels = framebuffer
+        .pixels()
+        .iter()
+        .map(|pixel| {
+            Color::new(
+                srgb_to_linear(pixel.r()),
+                srgb_to_linear(pixel.g()),
+                srgb_to_linear(pixel.b()),
+            )
+        })
+        .collect();
+    Framebuffer::new(framebuffer.width(), framebuffer.height(), pixels)
+}
+
+/// Clamps to [0,1], applies the sRGB transfer function and rounds to the bit depth
+fn quantize(pixels: &[Color], bit_depth: BitDepth) -> Vec<u16> {
+    let max_value = bit_depth.max_value() as FloatType;
+    pixels
+        .iter()
+        .flat_map(|pixel| [pixel.r(), pixel.g(), pixel.b()])
+        .map(|value| (linear_to_srgb(value.clamp(0.0, 1.0)) * max_value + 0.5) as u16)
+        .collect()
+}
+
+/// Small image with a flat half, which gives runs to the compressions, and a gradient half
+/// with values above one
+#[cfg(test)]
+fn test_framebuffer(width: usize, height: usize) -> Framebuffer {
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x as FloatType, y as FloatType)))
+        .map(|(x, y)| {
+            if x < width as FloatType / 2.0 {
+                Color::new(0.25, 0.5, 1.0)
+            } else {
+                Color::new(0.37 * x + 1.3 * y, 0.01 * y, 10.0 * x)
+            }
+        })
+        .collect();
+    Framebuffer::new(width, height, pixels)
+}
@@ -0,0 +1,151 @@
+use std::io::{self, Write};
+
+use super::Framebuffer;
+use crate::vec::{Color, FloatType};
+
+/// Writes the linear pixels as little endian color Portable FloatMap
+pub fn write_pfm(out: &mut impl Write, framebuffer: &Framebuffer) -> io::Result<()> {
+    // A negative scale marks little endian data
+    write!(
+        out,
+        "PF\n{} {}\n-1.0\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+
+    // Rows are stored bottom to top
+    for row in framebuffer.rows().rev() {
+        let bytes: Vec<u8> = row
+            .iter()
+            .flat_map(|pixel| [pixel.r(), pixel.g(), pixel.b()])
+            // FloatType may be f64, PFM is always single precision
+            .flat_map(|value| {
+                #[allow(clippy::unnecessary_cast)]
+                (value as f32).to_le_bytes()
+            })
+            .collect();
+        out.write_all(&bytes)?;
+    }
+    Ok(())
+}
+
+/// Reads a color (`PF`) or greyscale (`Pf`) Portable FloatMap
+pub fn read_pfm(bytes: &[u8]) -> io::Result<Framebuffer> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    // Four whitespace separated header tokens, followed by a single whitespace byte
+    let mut tokens = Vec::with_capacity(4);
+    let mut position = 0;
+    while tokens.len() < 4 {
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err(invalid("truncated PFM header"));
+        }
+        tokens.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    let data = bytes.get(position + 1..).unwrap_or(&[]);
+
+    let channels = match tokens[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+    let dimension = |token: &str| match token.parse::<usize>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(invalid("invalid PFM dimensions")),
+    };
+    let width = dimension(&tokens[1])?;
+    let height = dimension(&tokens[2])?;
+    let little_endian = match tokens[3].parse::<f32>() {
+        Ok(scale) if scale != 0.0 => scale < 0.0,
+        _ => return Err(invalid("invalid PFM scale")),
+    };
+
+    let expected = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels * 4))
+        .ok_or_else(|| invalid("invalid PFM dimensions"))?;
+    if data.len() < expected {
+        return Err(invalid("truncated PFM data"));
+    }
+    let values: Vec<FloatType> = data[..expected]
+        .chunks_exact(4)
+        .map(|bytes| {
+            let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+            let value = if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            value as FloatType
+        })
+        .collect();
+
+    // Rows are stored bottom to top
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks_exact(width * channels).rev() {
+        pixels.extend(row.chunks_exact(channels).map(|pixel| match pixel {
+            [r, g, b] => Color::new(*r, *g, *b),
+            _ => Color::new_eq(pixel[0]),
+        }));
+    }
+    Ok(Framebuffer::new(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::test_framebuffer;
+
+    fn assert_same_pixels(expected: &Framebuffer, actual: &Framebuffer) {
+        assert_eq!(
+            (actual.width(), actual.height()),
+            (expected.width(), expected.height())
+        );
+        assert!(expected
+            .pixels()
+            .iter()
+            .zip(actual.pixels())
+            .all(|(expected, actual)| expected.r() == actual.r()
+                && expected.g() == actual.g()
+                && expected.b() == actual.b()));
+    }
+
+    #[test]
+    fn round_trip_little_endian() {
+        let framebuffer = test_framebuffer(7, 5);
+        let mut bytes = Vec::new();
+        write_pfm(&mut bytes, &framebuffer).unwrap();
+        assert_same_pixels(&framebuffer, &read_pfm(&bytes).unwrap());
+    }
+
+    #[test]
+    fn round_trip_big_endian() {
+        let framebuffer = test_framebuffer(7, 5);
+        let mut bytes = b"PF\n7 5\n1.0\n".to_vec();
+        for row in framebuffer.rows().rev() {
+            for pixel in row {
+                for value in [pixel.r(), pixel.g(), pixel.b()] {
+                    #[allow(clippy::unnecessary_cast)]
+                    bytes.extend_from_slice(&(value as f32).to_be_bytes());
+                }
+            }
+        }
+        assert_same_pixels(&framebuffer, &read_pfm(&bytes).unwrap());
+    }
+
+    #[test]
+    fn greyscale() {
+        let mut bytes = b"Pf 2 1 -1.0\n".to_vec();
+        bytes.extend_from_slice(&0.5f32.to_le_bytes());
+        bytes.extend_from_slice(&2.0f32.to_le_bytes());
+        let framebuffer = read_pfm(&bytes).unwrap();
+        assert_eq!(framebuffer.pixels()[1].g(), 2.0);
+    }
+}
@@ -0,0 +1,418 @@
+//! Minimal PNG encoder (truecolor, no interlacing, adaptive filtering and zlib compression)
+//! and decoder for non-interlaced images of all color types
+use std::{
+    convert::TryFrom,
+    io::{self, Write},
+};
+
+use miniz_oxide::{deflate::compress_to_vec_zlib, inflate::decompress_to_vec_zlib};
+
+use super::{BitDepth, Framebuffer};
+use crate::vec::{Color, FloatType};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Color type 2: RGB triples
+const COLOR_TYPE_TRUECOLOR: u8 = 2;
+const COLOR_TYPE_GREYSCALE: u8 = 0;
+const COLOR_TYPE_INDEXED: u8 = 3;
+const COLOR_TYPE_GREYSCALE_ALPHA: u8 = 4;
+const COLOR_TYPE_TRUECOLOR_ALPHA: u8 = 6;
+
+/// Deflate level, 6 is the usual trade-off between size and speed
+const COMPRESSION_LEVEL: u8 = 6;
+
+/// Writes interleaved RGB samples as PNG tagged as sRGB
+pub fn write_png(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    bit_depth: BitDepth,
+    samples: &[u16],
+) -> io::Result<()> {
+    let dimension = |value: usize| {
+        u32::try_from(value)
+            .ok()
+            .filter(|&value| value > 0 && value <= i32::MAX as u32)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("PNG dimensions must be in 1..2^31, got {}", value),
+                )
+            })
+    };
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&dimension(width)?.to_be_bytes());
+    header.extend_from_slice(&dimension(height)?.to_be_bytes());
+    header.push(match bit_depth {
+        BitDepth::Eight => 8,
+        BitDepth::Sixteen => 16,
+    });
+    // Color type, deflate compression, adaptive filtering, no interlace
+    header.extend_from_slice(&[COLOR_TYPE_TRUECOLOR, 0, 0, 0]);
+
+    // Samples are big endian in PNG
+    let bytes: Vec<u8> = match bit_depth {
+        BitDepth::Eight => samples.iter().map(|&sample| sample as u8).collect(),
+        BitDepth::Sixteen => samples
+            .iter()
+            .flat_map(|sample| sample.to_be_bytes())
+            .collect(),
+    };
+    let bytes_per_pixel = match bit_depth {
+        BitDepth::Eight => 3,
+        BitDepth::Sixteen => 6,
+    };
+    let filtered = filter_scanlines(&bytes, width * bytes_per_pixel, bytes_per_pixel);
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    // Perceptual rendering intent. gAMA and cHRM carry the sRGB values for decoders that
+    // ignore the sRGB chunk, as recommended by the specification.
+    write_chunk(out, b"sRGB", &[0])?;
+    write_chunk(out, b"gAMA", &45455u32.to_be_bytes())?;
+    let chromaticities: Vec<u8> = [31270u32, 32900, 64000, 33000, 30000, 60000, 15000, 6000]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect();
+    write_chunk(out, b"cHRM", &chromaticities)?;
+    write_chunk(
+        out,
+        b"IDAT",
+        &compress_to_vec_zlib(&filtered, COMPRESSION_LEVEL),
+    )?;
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Reads a non-interlaced PNG, returns the still sRGB encoded samples scaled to [0,1]
+///
+/// Alpha is dropped and greyscale is expanded to RGB.
+pub fn read_png(bytes: &[u8]) -> io::Result<Framebuffer> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    if !bytes.starts_with(&SIGNATURE) {
+        return Err(invalid("not a PNG file".to_string()));
+    }
+    let mut data = &bytes[SIGNATURE.len()..];
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+    loop {
+        if data.len() < 12 {
+            return Err(invalid("truncated chunk".to_string()));
+        }
+        let length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        if data.len() < length + 12 {
+            return Err(invalid("truncated chunk".to_string()));
+        }
+        let kind = &data[4..8];
+        let chunk = &data[8..8 + length];
+        let crc = &data[8 + length..12 + length];
+        if !crc32_update(crc32_update(!0, kind), chunk)
+            != u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]])
+        {
+            return Err(invalid(format!(
+                "corrupt {} chunk (CRC mismatch)",
+                String::from_utf8_lossy(kind)
+            )));
+        }
+        match kind {
+            b"IHDR" if chunk.len() == 13 => header = Some(chunk),
+            b"PLTE" => palette = chunk,
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            _ => {}
+        }
+        data = &data[12 + length..];
+    }
+
+    let header = header.ok_or_else(|| invalid("missing IHDR chunk".to_string()))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let (bit_depth, color_type, interlace) = (header[8] as usize, header[9], header[12]);
+    if width == 0 || height == 0 {
+        return Err(invalid("invalid PNG dimensions".to_string()));
+    }
+    if interlace != 0 {
+        return Err(invalid("interlaced PNGs are not supported".to_string()));
+    }
+    let channels = match (color_type, bit_depth) {
+        (COLOR_TYPE_GREYSCALE, 1 | 2 | 4 | 8 | 16) => 1,
+        (COLOR_TYPE_TRUECOLOR, 8 | 16) => 3,
+        (COLOR_TYPE_INDEXED, 1 | 2 | 4 | 8) => 1,
+        (COLOR_TYPE_GREYSCALE_ALPHA, 8 | 16) => 2,
+        (COLOR_TYPE_TRUECOLOR_ALPHA, 8 | 16) => 4,
+        _ => {
+            return Err(invalid(format!(
+                "invalid color type {} with bit depth {}",
+                color_type, bit_depth
+            )))
+        }
+    };
+    if color_type == COLOR_TYPE_INDEXED && palette.is_empty() {
+        return Err(invalid("missing PLTE chunk".to_string()));
+    }
+
+    let filtered = decompress_to_vec_zlib(&compressed)
+        .map_err(|error| invalid(format!("corrupt image data: {:?}", error.status)))?;
+    let bits_per_pixel = channels * bit_depth;
+    // Checked, the dimensions of crafted headers overflow
+    let stride = width
+        .checked_mul(bits_per_pixel)
+        .map(|bits| bits.div_ceil(8))
+        .ok_or_else(|| invalid("invalid PNG dimensions".to_string()))?;
+    let size = (stride + 1)
+        .checked_mul(height)
+        .ok_or_else(|| invalid("invalid PNG dimensions".to_string()))?;
+    if filtered.len() < size {
+        return Err(invalid("truncated image data".to_string()));
+    }
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8).max(1);
+    let scanlines = unfilter_scanlines(&filtered, height, stride, bytes_per_pixel)
+        .ok_or_else(|| invalid("invalid filter type".to_string()))?;
+
+    let max_value = ((1u32 << bit_depth) - 1) as FloatType;
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in scanlines.chunks_exact(stride) {
+        let sample = |index: usize| -> u16 {
+            match bit_depth {
+                16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+                8 => row[index] as u16,
+                // Sub-byte samples are packed from the most significant bit
+                _ => {
+                    let bit = index * bit_depth;
+                    let shift = 8 - bit_depth - bit % 8;
+                    ((row[bit / 8] >> shift) & ((1 << bit_depth) - 1) as u8) as u16
+                }
+            }
+        };
+        for x in 0..width {
+            let first = x * channels;
+            let pixel = match color_type {
+                COLOR_TYPE_INDEXED => {
+                    let index = sample(first) as usize * 3;
+                    let entry = palette.get(index..index + 3).ok_or_else(|| {
+                        invalid(format!("palette index {} out of range", index / 3))
+                    })?;
+                    Color::new(
+                        entry[0] as FloatType,
+                        entry[1] as FloatType,
+                        entry[2] as FloatType,
+                    ) / 255.0
+                }
+                COLOR_TYPE_GREYSCALE | COLOR_TYPE_GREYSCALE_ALPHA => {
+                    Color::new_eq(sample(first) as FloatType / max_value)
+                }
+                _ => {
+                    Color::new(
+                        sample(first) as FloatType,
+                        sample(first + 1) as FloatType,
+                        sample(first + 2) as FloatType,
+                    ) / max_value
+                }
+            };
+            pixels.push(pixel);
+        }
+    }
+    Ok(Framebuffer::new(width, height, pixels))
+}
+
+/// Reverses the per scanline filters, returns the raw scanlines without filter bytes
+fn unfilter_scanlines(
+    filtered: &[u8],
+    height: usize,
+    stride: usize,
+    bytes_per_pixel: usize,
+) -> Option<Vec<u8>> {
+    let mut scanlines = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = filtered[y * (stride + 1)];
+        let line = &filtered[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (previous, current) = scanlines.split_at_mut(y * stride);
+        let prior = if y == 0 {
+            None
+        } else {
+            Some(&previous[(y - 1) * stride..])
+        };
+        let current = &mut current[..stride];
+        for i in 0..stride {
+            let left = if i >= bytes_per_pixel {
+                current[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = prior.map_or(0, |prior| prior[i]);
+            let up_left = match prior {
+                Some(prior) if i >= bytes_per_pixel => prior[i - bytes_per_pixel],
+                _ => 0,
+            };
+            let prediction = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            current[i] = line[i].wrapping_add(prediction);
+        }
+    }
+    Some(scanlines)
+}
+
+fn write_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = !crc32_update(crc32_update(!0, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// Prefixes every scanline with the filter type that gives the smallest sum of absolute
+/// differences, the heuristic suggested by the specification
+fn filter_scanlines(bytes: &[u8], stride: usize, bytes_per_pixel: usize) -> Vec<u8> {
+    let mut filtered = Vec::with_capacity(bytes.len() + bytes.len() / stride);
+    let zeros = vec![0; stride];
+    let mut candidate = vec![0; stride];
+    let mut best = vec![0; stride];
+
+    for (y, row) in bytes.chunks_exact(stride).enumerate() {
+        let prior = if y == 0 {
+            &zeros[..]
+        } else {
+            &bytes[(y - 1) * stride..y * stride]
+        };
+
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5 {
+            for i in 0..stride {
+                let left = if i >= bytes_per_pixel {
+                    row[i - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let up = prior[i];
+                let up_left = if i >= bytes_per_pixel {
+                    prior[i - bytes_per_pixel]
+                } else {
+                    0
+                };
+                let prediction = match filter {
+                    0 => 0,
+                    1 => left,
+                    2 => up,
+                    3 => ((left as u16 + up as u16) / 2) as u8,
+                    _ => paeth(left, up, up_left),
+                };
+                candidate[i] = row[i].wrapping_sub(prediction);
+            }
+            let score = candidate
+                .iter()
+                .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                .sum();
+            if score < best_score {
+                best_score = score;
+                best_filter = filter;
+                std::mem::swap(&mut best, &mut candidate);
+            }
+        }
+
+        filtered.push(best_filter);
+        filtered.extend_from_slice(&best);
+    }
+    filtered
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance_left = (estimate - left as i16).abs();
+    let distance_up = (estimate - up as i16).abs();
+    let distance_up_left = (estimate - up_left as i16).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// CRC-32 (ISO 3309) lookup table, computed at compile time
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+/// Feeds bytes into a running CRC, which starts at and is finished with `!0`
+fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(crc, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bit_depth: BitDepth) {
+        let (width, height) = (7, 5);
+        let max_value = bit_depth.max_value() as usize;
+        let samples: Vec<u16> = (0..width * height * 3)
+            .map(|index| (index * 7919 % (max_value + 1)) as u16)
+            .collect();
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, width, height, bit_depth, &samples).unwrap();
+
+        let framebuffer = read_png(&bytes).unwrap();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (width, height));
+        let read: Vec<u16> = framebuffer
+            .pixels()
+            .iter()
+            .flat_map(|pixel| [pixel.r(), pixel.g(), pixel.b()])
+            .map(|value| (value * max_value as FloatType).round() as u16)
+            .collect();
+        assert_eq!(read, samples);
+    }
+
+    #[test]
+    fn round_trip_8_bit() {
+        round_trip(BitDepth::Eight);
+    }
+
+    #[test]
+    fn round_trip_16_bit() {
+        round_trip(BitDepth::Sixteen);
+    }
+
+    #[test]
+    fn rejects_overflowing_dimensions() {
+        let mut header = Vec::new();
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&u32::MAX.to_be_bytes());
+        header.extend_from_slice(&[16, COLOR_TYPE_TRUECOLOR_ALPHA, 0, 0, 0]);
+        let mut bytes = SIGNATURE.to_vec();
+        write_chunk(&mut bytes, b"IHDR", &header).unwrap();
+        write_chunk(&mut bytes, b"IDAT", &compress_to_vec_zlib(&[0; 16], 6)).unwrap();
+        write_chunk(&mut bytes, b"IEND", &[]).unwrap();
+
+        let error = read_png(&bytes).err().expect("crafted header was accepted");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
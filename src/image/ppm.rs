@@ -0,0 +1,147 @@
+use std::io::{self, Write};
+
+use super::{BitDepth, Framebuffer};
+use crate::vec::{Color, FloatType};
+
+/// Writes interleaved RGB samples as ASCII PPM
+pub fn write_ppm(
+    out: &mut impl Write,
+    width: usize,
+    height: usize,
+    bit_depth: BitDepth,
+    samples: &[u16],
+) -> io::Result<()> {
+    // Colors in Ascii
+    writeln!(out, "P3")?;
+    // Columns and Rows
+    writeln!(out, "{} {}", width, height)?;
+    // Max Color
+    writeln!(out, "{}", bit_depth.max_value())?;
+
+    for pixel in samples.chunks_exact(3) {
+        writeln!(out, "{} {} {}", pixel[0], pixel[1], pixel[2])?;
+    }
+    Ok(())
+}
+
+/// Reads an ASCII (P2, P3) or binary (P5, P6) greyscale or color Netpbm image, returns the
+/// still sRGB encoded samples scaled to [0,1]
+pub fn read_ppm(bytes: &[u8]) -> io::Result<Framebuffer> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let (channels, binary) = match bytes.get(..2) {
+        Some(b"P2") => (1, false),
+        Some(b"P3") => (3, false),
+        Some(b"P5") => (1, true),
+        Some(b"P6") => (3, true),
+        _ => return Err(invalid("not a PPM or PGM file")),
+    };
+
+    // Whitespace separated tokens, comments run from # to the end of the line
+    let mut position = 2;
+    let mut next_token = || -> Option<&[u8]> {
+        loop {
+            while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if bytes.get(position) != Some(&b'#') {
+                break;
+            }
+            while position < bytes.len() && bytes[position] != b'\n' {
+                position += 1;
+            }
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            None
+        } else {
+            Some(&bytes[start..position])
+        }
+    };
+    let mut number = |message: &str| -> io::Result<usize> {
+        next_token()
+            .and_then(|token| std::str::from_utf8(token).ok()?.parse().ok())
+            .ok_or_else(|| invalid(message))
+    };
+
+    let width = number("invalid PPM width")?;
+    let height = number("invalid PPM height")?;
+    let max_value = number("invalid PPM maximum value")?;
+    if width == 0 || height == 0 || !(1..=u16::MAX as usize).contains(&max_value) {
+        return Err(invalid("invalid PPM header"));
+    }
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(|| invalid("invalid PPM dimensions"))?;
+
+    let samples: Vec<usize> = if binary {
+        // A single whitespace byte separates the header from the samples, which are big
+        // endian if they need two bytes
+        let data = bytes.get(position + 1..).unwrap_or(&[]);
+        let sample_size = if max_value > u8::MAX as usize { 2 } else { 1 };
+        if data.len() < count * sample_size {
+            return Err(invalid("truncated PPM data"));
+        }
+        data[..count * sample_size]
+            .chunks_exact(sample_size)
+            .map(|sample| match sample {
+                [high, low] => (*high as usize) << 8 | *low as usize,
+                _ => sample[0] as usize,
+            })
+            .collect()
+    } else {
+        (0..count)
+            .map(|_| number("truncated or invalid PPM data"))
+            .collect::<io::Result<_>>()?
+    };
+
+    let scale = max_value as FloatType;
+    let pixels = samples
+        .chunks_exact(channels)
+        .map(|pixel| match pixel {
+            [r, g, b] => Color::new(*r as FloatType, *g as FloatType, *b as FloatType) / scale,
+            _ => Color::new_eq(pixel[0] as FloatType / scale),
+        })
+        .map(|pixel| pixel.min(&Color::new_eq(1.0)))
+        .collect();
+    Ok(Framebuffer::new(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bit_depth: BitDepth) {
+        let (width, height) = (7, 5);
+        let max_value = bit_depth.max_value() as usize;
+        let samples: Vec<u16> = (0..width * height * 3)
+            .map(|index| (index * 7919 % (max_value + 1)) as u16)
+            .collect();
+        let mut bytes = Vec::new();
+        write_ppm(&mut bytes, width, height, bit_depth, &samples).unwrap();
+
+        let framebuffer = read_ppm(&bytes).unwrap();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (width, height));
+        let read: Vec<u16> = framebuffer
+            .pixels()
+            .iter()
+            .flat_map(|pixel| [pixel.r(), pixel.g(), pixel.b()])
+            .map(|value| (value * max_value as FloatType).round() as u16)
+            .collect();
+        assert_eq!(read, samples);
+    }
+
+    #[test]
+    fn round_trip_8_bit() {
+        round_trip(BitDepth::Eight);
+    }
+
+    #[test]
+    fn round_trip_16_bit() {
+        round_trip(BitDepth::Sixteen);
+    }
+}
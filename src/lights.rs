@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{AreaLight, HitRecord, Hittable, HittableList},
+    punctual::PunctualLight,
+    ray::Ray,
+    utils::random_float,
+    vec::{FloatType, Point3, Vector3},
+};
+
+/// Emissive objects and punctual lights of the scene, which are sampled explicitly
+#[derive(Default)]
+pub struct Lights {
+    objects: Vec<Arc<dyn Hittable>>,
+    punctual: Vec<PunctualLight>,
+}
+
+impl Lights {
+    /// Shares the objects of the world that are lights with the returned lights
+    ///
+    /// The world keeps wrappers that mark their hits with the index of the light.
+    pub fn extract(world: HittableList, punctual: Vec<PunctualLight>) -> (HittableList, Lights) {
+        let mut lights = Lights {
+            objects: Vec::new(),
+            punctual,
+        };
+        let mut remaining = HittableList::default();
+        for object in world.objects {
+            if object.as_light().is_some() {
+                let object: Arc<dyn Hittable> = Arc::from(object);
+                remaining.add(Box::new(LightObject {
+                    index: lights.objects.len(),
+                    object: Arc::clone(&object),
+                }));
+                lights.objects.push(object);
+            } else {
+                remaining.add(object);
+            }
+        }
+        (remaining, lights)
+    }
+
+    /// Number of emissive objects
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn punctual(&self) -> &[PunctualLight] {
+        &self.punctual
+    }
+
+    /// Picks a light uniformly and a direction from `origin` towards it, returns the index of
+    /// the light, the direction and its density per solid angle
+    pub fn sample(&self, origin: &Point3) -> Option<(usize, Vector3, FloatType)> {
+        if self.objects.is_empty() {
+            return None;
+        }
+        let index = ((random_float() * self.len() as FloatType) as usize).min(self.len() - 1);
+        let direction = self.light(index).random(origin);
+        Some((index, direction, self.pdf(index, origin, &direction)))
+    }
+
+    /// Density of `sample` picking the direction towards the light `index`
+    pub fn pdf(&self, index: usize, origin: &Point3, direction: &Vector3) -> FloatType {
+        self.light(index).pdf_value(origin, direction) / self.len() as FloatType
+    }
+
+    fn light(&self, index: usize) -> &dyn AreaLight {
+        self.objects[index]
+            .as_light()
+            .expect("Only lights are extracted from the world")
+    }
+}
+
+/// Emissive object in the world that tags its hits with its index in `Lights`
+struct LightObject {
+    index: usize,
+    object: Arc<dyn Hittable>,
+}
+
+impl Hittable for LightObject {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
+        let mut hit_record = self.object.hit(r, t_min, t_max)?;
+        hit_record.light = Some(self.index);
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Material,
+        sphere::Sphere,
+        utils::reseed_rng,
+        vec::{Color, PI},
+    };
+
+    /// Two sphere lights of different size and distance and a diffuse sphere
+    fn lights() -> Lights {
+        let mut world = HittableList::default();
+        let light = Material::DiffuseLight(Color::new_eq(4.0));
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 3.0, 0.0),
+            1.0,
+            light.clone(),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(-5.0, 0.5, 2.0),
+            0.5,
+            Material::Lambertian(Color::new_eq(0.5).into()),
+        )));
+        world.add(Box::new(Sphere::new(
+            Point3::new(4.0, 0.0, -1.0),
+            2.0,
+            light,
+        )));
+        let (_, lights) = Lights::extract(world, Vec::new());
+        assert_eq!(lights.len(), 2);
+        lights
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        reseed_rng(1);
+        let lights = lights();
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        let count = 400_000;
+        // Monte Carlo integral over the sphere of directions with uniform samples
+        let integral: FloatType = (0..count)
+            .map(|_| {
+                let direction = Vector3::random_unit_vector();
+                (0..lights.len())
+                    .map(|index| lights.pdf(index, &origin, &direction))
+                    .sum::<FloatType>()
+            })
+            .sum::<FloatType>()
+            * 4.0
+            * PI
+            / count as FloatType;
+        assert!(
+            (integral - 1.0).abs() < 0.02,
+            "pdf integrates to {}",
+            integral
+        );
+    }
+
+    #[test]
+    fn pdf_matches_sample_density() {
+        reseed_rng(2);
+        let lights = lights();
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        // Solid angle of each light, estimated with uniform directions
+        let count = 400_000;
+        let mut solid_angles = [0.0; 2];
+        for _ in 0..count {
+            let direction = Vector3::random_unit_vector();
+            for (index, solid_angle) in solid_angles.iter_mut().enumerate() {
+                if lights.light(index).pdf_value(&origin, &direction) > 0.0 {
+                    *solid_angle += 4.0 * PI / count as FloatType;
+                }
+            }
+        }
+
+        // The same solid angles estimated with the light samples and their pdf
+        let count = 100_000;
+        let mut estimates = [0.0; 2];
+        for _ in 0..count {
+            let (index, direction, pdf) = lights.sample(&origin).unwrap();
+            assert!(pdf > 0.0, "Sampled direction misses the light");
+            assert_eq!(pdf, lights.pdf(index, &origin, &direction));
+            estimates[index] += 1.0 / pdf / count as FloatType;
+        }
+        for index in 0..2 {
+            let (expected, estimate) = (solid_angles[index], estimates[index]);
+            assert!(
+                (estimate - expected).abs() < 0.02 * expected,
+                "light {}: {} vs {}",
+                index,
+                estimate,
+                expected
+            );
+        }
+    }
+}
@@ -1,104 +1,392 @@
+mod aabb;
+mod bvh;
 mod camera;
+mod cli;
+mod constant_medium;
+mod distribution;
+mod environment;
 mod hittable;
+mod image;
+mod lights;
 mod material;
+mod mesh;
+mod microfacet;
+mod normal_map;
+mod obj;
+mod onb;
+mod perlin;
+mod ply;
+mod principled;
+mod punctual;
 mod ray;
+mod scene;
+mod scene_file;
 mod scenes;
+mod sky;
 mod sphere;
+mod texture;
+mod triangle;
 mod utils;
 mod vec;
 
-use camera::Camera;
-use hittable::Hittable;
+use bvh::BvhNode;
+use cli::Command;
+use environment::Environment;
+use hittable::{HitRecord, Hittable};
+use image::{write_image, Framebuffer, ImageFormat};
+use lights::Lights;
 use ray::Ray;
+use scene::Scene;
 use scenes::*;
 use utils::*;
 use vec::*;
 
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+};
+
 use rayon::prelude::*;
 
-fn ray_color(r: &Ray, world: &impl Hittable, depth: usize) -> Color {
+/// Power heuristic with beta 2 for combining two sampling strategies
+fn mis_weight(pdf: FloatType, other_pdf: FloatType) -> FloatType {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Radiance arriving along the ray
+///
+/// `scattering_pdf` is the density with which the previous hit sampled the ray, if that
+/// hit also sampled the lights and the environment. Light found by the ray is then weighted
+/// against those light samples.
+fn ray_color(
+    r: &Ray,
+    world: &impl Hittable,
+    lights: &Lights,
+    environment: &Environment,
+    depth: usize,
+    scattering_pdf: Option<FloatType>,
+) -> Color {
+    trace(r, world, lights, environment, depth, scattering_pdf).0
+}
+
+/// `ray_color` that also returns the distance and the shading normal of the first hit
+fn trace(
+    r: &Ray,
+    world: &impl Hittable,
+    lights: &Lights,
+    environment: &Environment,
+    depth: usize,
+    scattering_pdf: Option<FloatType>,
+) -> (Color, Option<(FloatType, Vector3)>) {
     // If we've exceeded the ray bounce limit no more light is gathered
     if depth == 0 {
-        return Color::new_eq(0.0);
+        return (Color::new_eq(0.0), None);
     }
 
-    if let Some(hit_record) = world.hit(r, 0.001, FloatType::MAX) {
-        if let Some((attenuation, scattered)) = hit_record.material.scatter(r, &hit_record) {
-            attenuation * ray_color(&scattered, world, depth - 1)
-        } else {
-            Color::new_eq(0.0)
+    let hit_record = match world.hit(r, 0.001, FloatType::MAX) {
+        Some(hit_record) => hit_record.apply_normal_map(),
+        None => {
+            let radiance = environment.radiance(&r.direction);
+            let color = match scattering_pdf {
+                Some(pdf) => mis_weight(pdf, environment.pdf(&r.direction)) * radiance,
+                None => radiance,
+            };
+            return (color, None);
         }
-    } else {
-        let unit_direction = r.direction.unit_vector();
-        let t = 0.5 * (unit_direction.y + 1.0);
-        (1.0 - t) * Color::new_eq(1.0) + t * Color::new(0.5, 0.7, 1.0)
+    };
+    let color = shade(
+        r,
+        &hit_record,
+        world,
+        lights,
+        environment,
+        depth,
+        scattering_pdf,
+    );
+    let distance = hit_record.t * r.direction.length();
+    (color, Some((distance, hit_record.normal)))
+}
+
+/// Radiance leaving the hit towards the origin of the ray
+fn shade(
+    r: &Ray,
+    hit_record: &HitRecord,
+    world: &impl Hittable,
+    lights: &Lights,
+    environment: &Environment,
+    depth: usize,
+    scattering_pdf: Option<FloatType>,
+) -> Color {
+    let material = hit_record.material;
+    let mut emitted = material.emitted();
+    if let (Some(pdf), Some(light)) = (scattering_pdf, hit_record.light) {
+        emitted = mis_weight(pdf, lights.pdf(light, &r.origin, &r.direction)) * emitted;
+    }
+    let scatter = material.scatter(r, hit_record);
+    if material.is_emissive() && scatter.is_none() {
+        return emitted;
+    }
+    // Mirror-like materials can't be combined with light samples
+    if material.is_specular() {
+        return match scatter {
+            Some(scatter) => {
+                emitted
+                    + scatter.attenuation()
+                        * ray_color(&scatter.ray, world, lights, environment, depth - 1, None)
+            }
+            None => emitted,
+        };
+    }
+
+    let mut direct = Color::new_eq(0.0);
+    if let Some((light, direction, light_pdf)) = lights.sample(&hit_record.point) {
+        let material_pdf = material.pdf(r, hit_record, &direction);
+        if material_pdf > 0.0 && light_pdf > 0.0 {
+            // Only counts if the sampled light is the first thing along the shadow ray
+            let shadow_ray = Ray::new(hit_record.point, direction);
+            if let Some(light_hit) = world.hit(&shadow_ray, 0.001, FloatType::MAX) {
+                if light_hit.light == Some(light) {
+                    direct += mis_weight(light_pdf, material_pdf) / light_pdf
+                        * material.eval(r, hit_record, &direction)
+                        * light_hit.material.emitted();
+                }
+            }
+        }
+    }
+    // Delta lights can only be reached by shadow rays, so they need no MIS
+    for light in lights.punctual() {
+        if let Some((direction, distance, radiance)) = light.illuminate(&hit_record.point) {
+            let value = material.eval(r, hit_record, &direction);
+            if value.is_near_zero() {
+                continue;
+            }
+            let shadow_ray = Ray::new(hit_record.point, direction);
+            if world.hit(&shadow_ray, 0.001, distance * 0.999).is_none() {
+                direct += value * radiance;
+            }
+        }
+    }
+    if let Some((direction, radiance, light_pdf)) = environment.sample() {
+        let material_pdf = material.pdf(r, hit_record, &direction);
+        if material_pdf > 0.0
+            && world
+                .hit(
+                    &Ray::new(hit_record.point, direction),
+                    0.001,
+                    FloatType::MAX,
+                )
+                .is_none()
+        {
+            direct += mis_weight(light_pdf, material_pdf) / light_pdf
+                * material.eval(r, hit_record, &direction)
+                * radiance;
+        }
+    }
+
+    match scatter {
+        // The mirror-like lobe of a layered material, light samples only covered the others
+        Some(scatter) if scatter.is_specular => {
+            emitted
+                + direct
+                + scatter.attenuation()
+                    * ray_color(&scatter.ray, world, lights, environment, depth - 1, None)
+        }
+        // A sample lost below a rough surface still leaves the light samples
+        Some(scatter) if scatter.pdf > 0.0 => {
+            emitted
+                + direct
+                + scatter.attenuation()
+                    * ray_color(
+                        &scatter.ray,
+                        world,
+                        lights,
+                        environment,
+                        depth - 1,
+                        Some(scatter.pdf),
+                    )
+        }
+        _ => emitted + direct,
     }
 }
 
 fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Render(options)) => options,
+        Ok(Command::ListScenes) => {
+            for scene in BUILTIN_SCENES {
+                println!("{:<12} {}", scene.name, scene.description);
+            }
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(error) => {
+            eprintln!("Error: {}\n\n{}", error, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Some(threads) = options.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("Thread pool is only configured once");
+    }
+    if let Some(seed) = options.seed {
+        reseed_rng(seed);
+    }
+
+    // Builtin scene by name, otherwise a scene file or model
+    let scene_name = options.scene.as_deref().unwrap_or("random");
+    let scene = match builtin_scene(scene_name) {
+        Some(builtin) => (builtin.build)(),
+        None => file_scene(scene_name).unwrap_or_else(|error| {
+            eprintln!("Error: {}", error);
+            if std::path::Path::new(scene_name).extension().is_none() {
+                eprintln!("Use --list-scenes to see the builtin scenes");
+            }
+            std::process::exit(1);
+        }),
+    };
+    let Scene {
+        mut image,
+        camera,
+        world,
+        environment,
+        lights,
+        warnings,
+    } = scene;
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+    options.apply(&mut image);
+
     // Image
-    //pub const ASPECT_RATIO: FloatType = 16.0 / 9.0;
-    const ASPECT_RATIO: FloatType = 3.0 / 2.0;
-    const IMAGE_WIDTH: usize = 1200;
-    const IMAGE_HEIGHT: usize = (IMAGE_WIDTH as FloatType / ASPECT_RATIO) as usize;
-    const SAMPLES_PER_PIXEL: usize = 10;
-    const MAX_DEPTH: usize = 50;
+    let image_width = image.width;
+    let image_height = image.height();
+    let samples_per_pixel = image.samples_per_pixel;
+    let max_depth = image.max_depth;
 
     // World
-    let world = random_scene();
-
-    // Camera
-    let look_from = Point3::new(13.0, 2.0, 3.0);
-    let look_at = Point3::new(0.0, 0.0, 0.0);
-    let view_up = Vector3::new(0.0, 1.0, 0.0);
-    let dist_to_focus = 10.0; //(look_from - look_at).length();
-    let aperture = 0.1; //2.0;
-    let cam = Camera::new(
-        look_from,
-        look_at,
-        view_up,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
+    let (world, lights) = Lights::extract(world, lights);
+    let world = BvhNode::with_split_method(world, options.split_method);
+    if options.stats {
+        eprintln!("{}", world.stats());
+    }
+    eprintln!(
+        "Lights: {} emissive objects, {} punctual",
+        lights.len(),
+        lights.punctual().len()
     );
 
-    // Render
+    // Camera
+    let cam = camera.build(image.aspect_ratio);
 
-    // Colors in Ascii
-    println!("P3");
-    // Columns and Rows
-    println!("{} {}", IMAGE_WIDTH, IMAGE_HEIGHT);
-    // 255 for Max Color
-    println!("255");
+    // Only EXR can store the distance and normal channels
+    let with_aovs = options.format == ImageFormat::Exr;
 
-    let image = (0..IMAGE_HEIGHT)
+    // Render
+    let rendered = (0..image_height)
         .into_par_iter()
         .rev()
         .flat_map(|j| {
             eprint!("\rScanlines remaining: {} ", j);
-            (0..IMAGE_WIDTH)
-                .into_iter()
+            if let Some(seed) = options.seed {
+                reseed_rng(seed ^ (j as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            }
+            (0..image_width)
                 .map(|i| {
-                    let pixel_color: Color = (0..SAMPLES_PER_PIXEL)
+                    let mut distance = FloatType::INFINITY;
+                    let mut normal = Vector3::new_eq(0.0);
+                    let pixel_color: Color = (0..samples_per_pixel)
                         .map(|_| {
-                            let u =
-                                (i as FloatType + random_float()) / (IMAGE_WIDTH - 1) as FloatType;
-                            let v =
-                                (j as FloatType + random_float()) / (IMAGE_HEIGHT - 1) as FloatType;
+                            let u = (i as FloatType + random_float())
+                                / (image_width - 1).max(1) as FloatType;
+                            let v = (j as FloatType + random_float())
+                                / (image_height - 1).max(1) as FloatType;
                             let r = cam.get_ray(u, v);
-                            ray_color(&r, &world, MAX_DEPTH)
+                            let (color, first_hit) =
+                                trace(&r, &world, &lights, &environment, max_depth, None);
+                            if let Some((hit_distance, hit_normal)) = first_hit {
+                                distance = distance.min(hit_distance);
+                                normal += hit_normal;
+                            }
+                            color
                         })
                         //.reduce(|| Color::new_eq(0.0), |a, b| a + b);
                         .fold(Color::new_eq(0.0), |acc, b| acc + b);
 
-                    pixel_color.print_color(SAMPLES_PER_PIXEL)
+                    if normal.length_squared() > 0.0 {
+                        normal = normal.unit_vector();
+                    }
+                    (
+                        pixel_color / samples_per_pixel as FloatType,
+                        distance,
+                        normal,
+                    )
                 })
-                .collect::<Vec<(u8, u8, u8)>>()
+                .collect::<Vec<_>>()
         })
-        .collect::<Vec<(u8, u8, u8)>>();
-    for pixel in image {
-        println!("{} {} {}", pixel.0, pixel.1, pixel.2);
-    }
+        .collect::<Vec<_>>();
     eprintln!("\nDone.");
+
+    let pixels = rendered.iter().map(|pixel| pixel.0).collect();
+    let mut framebuffer = Framebuffer::new(image_width, image_height, pixels);
+    if with_aovs {
+        // Closest distance to the camera, infinite where nothing was hit
+        framebuffer.add_channel("Z", rendered.iter().map(|pixel| pixel.1).collect());
+        // Averaged normal facing the camera
+        for (axis, name) in ["N.X", "N.Y", "N.Z"].iter().enumerate() {
+            let values = rendered.iter().map(|pixel| pixel.2[axis]).collect();
+            framebuffer.add_channel(*name, values);
+        }
+    }
+
+    let write = |out: &mut dyn Write| {
+        write_image(
+            &mut BufWriter::new(out),
+            options.format,
+            &options.encode,
+            &framebuffer,
+        )
+    };
+    let result = match &options.output {
+        Some(path) => File::create(path).and_then(|mut file| write(&mut file)),
+        None => write(&mut io::stdout().lock()),
+    };
+    if let Err(error) = result {
+        let target = options
+            .output
+            .as_ref()
+            .map_or("stdout".to_string(), |path| path.display().to_string());
+        eprintln!("Error: writing {}: {}", target, error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mis_weights_sum_to_one() {
+        let pdfs = [1.0e-3, 0.05, 0.3, 1.0, 2.5, 40.0, 1.0e4];
+        for &a in &pdfs {
+            for &b in &pdfs {
+                let sum = mis_weight(a, b) + mis_weight(b, a);
+                assert!((sum - 1.0).abs() < 1.0e-5, "{} and {}: {}", a, b, sum);
+            }
+            // A strategy that can't sample the direction leaves everything to the other
+            assert_eq!(mis_weight(a, 0.0), 1.0);
+            assert_eq!(mis_weight(0.0, a), 0.0);
+        }
+        assert_eq!(mis_weight(0.0, 0.0), 0.0);
+    }
 }
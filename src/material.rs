@@ -1,19 +1,26 @@
+use std::sync::Arc;
+
 use crate::{
     hittable::HitRecord,
+    microfacet::{Coat, Conductor, MicrofacetSample, RoughDielectric},
+    normal_map::NormalMap,
+    onb::Onb,
+    principled::Principled,
     ray::Ray,
+    texture::Texture,
     utils::random_float,
-    vec::{Color, FloatType, Vector3},
+    vec::{Color, FloatType, Vector3, PI},
 };
 
 #[derive(Clone)]
 /// Metal type with Albedo & Reflection Fuzz
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: FloatType,
 }
 
 impl Metal {
-    pub fn new(albedo: Color, fuzz: FloatType) -> Self {
+    pub fn new(albedo: Arc<dyn Texture>, fuzz: FloatType) -> Self {
         Self {
             albedo,
             fuzz: if fuzz < 1.0 { fuzz } else { 1.0 },
@@ -33,25 +40,196 @@ impl Dielectric {
 
 #[derive(Clone)]
 pub enum Material {
-    Lambertian(Color),
+    /// Diffuse with the texture as albedo
+    Lambertian(Arc<dyn Texture>),
+    /// Lambertian with the interpolated vertex color of the hit as albedo (grey if it has none)
+    VertexColor,
     Metal(Metal),
     // Index of Refraction
     Dielectric(FloatType),
+    /// Emits the radiance on both sides and absorbs all incoming light
+    DiffuseLight(Color),
+    /// Scatters equally into all directions with the texture as albedo, the phase function
+    /// of a `ConstantMedium`
+    Isotropic(Arc<dyn Texture>),
+    /// Metal with a GGX microfacet surface
+    Conductor(Conductor),
+    /// Glass with a GGX microfacet surface
+    RoughDielectric(RoughDielectric),
+    /// Layered material blending diffuse, metal, glass, sheen and a clear coat
+    Principled(Principled),
+    /// The base material under a clear coat on the outside of the surface, e.g. varnish or
+    /// the lacquer of car paint
+    Coated {
+        base: Box<Material>,
+        coat: Coat,
+    },
+    /// The base material with a normal or bump map, see `HitRecord::apply_normal_map`
+    NormalMapped {
+        base: Box<Material>,
+        map: Arc<NormalMap>,
+    },
+}
+
+/// Result of sampling a material
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// BSDF times the cosine between scattered direction and normal
+    pub value: Color,
+    /// Density of the scattered direction per solid angle
+    pub pdf: FloatType,
+    /// Dirac (mirror-like) lobes can't be evaluated for other directions and aren't combined
+    /// with light sampling. Their `value` is the attenuation and `pdf` is 1.
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    fn specular(ray: Ray, attenuation: Color) -> Self {
+        Self {
+            ray,
+            value: attenuation,
+            pdf: 1.0,
+            is_specular: true,
+        }
+    }
+
+    /// Path throughput of the scattered ray
+    pub fn attenuation(&self) -> Color {
+        self.value / self.pdf
+    }
 }
 
 impl Material {
-    /// Returns attenuation color and scattered ray if found
-    pub fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
+    /// Albedo of the diffuse materials, None for all others
+    fn diffuse_albedo(&self, hit_record: &HitRecord) -> Option<Color> {
         match self {
             Material::Lambertian(albedo) => {
-                let mut scatter_direction = hit_record.normal + Vector3::random_unit_vector();
+                Some(albedo.value(hit_record.u, hit_record.v, &hit_record.point))
+            }
+            Material::VertexColor => Some(hit_record.color.unwrap_or_else(|| Color::new_eq(0.5))),
+            _ => None,
+        }
+    }
 
-                // Catch degenerate scatter direction
-                if scatter_direction.is_near_zero() {
-                    scatter_direction = hit_record.normal;
-                }
+    /// Only has mirror-like lobes, which light samples can't reach
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Metal(_) | Material::Dielectric(_) => true,
+            Material::Conductor(conductor) => conductor.is_smooth(),
+            Material::RoughDielectric(dielectric) => dielectric.is_smooth(),
+            Material::Coated { base, coat } => coat.is_smooth() && base.is_specular(),
+            Material::NormalMapped { base, .. } => base.is_specular(),
+            _ => false,
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Material::DiffuseLight(_) => true,
+            Material::NormalMapped { base, .. } => base.is_emissive(),
+            _ => false,
+        }
+    }
+
+    /// Radiance emitted towards the incoming ray, the same everywhere on the surface
+    pub fn emitted(&self) -> Color {
+        match self {
+            Material::DiffuseLight(radiance) => *radiance,
+            Material::NormalMapped { base, .. } => base.emitted(),
+            _ => Color::new_eq(0.0),
+        }
+    }
+
+    /// BSDF times cosine for light arriving from `direction` and leaving towards the
+    /// origin of `ray_in`, zero for specular materials
+    pub fn eval(&self, ray_in: &Ray, hit_record: &HitRecord, direction: &Vector3) -> Color {
+        if let Some(albedo) = self.diffuse_albedo(hit_record) {
+            let cosine = hit_record.normal.dot(&direction.unit_vector());
+            return cosine.max(0.0) / PI * albedo;
+        }
+        match self {
+            Material::Conductor(conductor) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                conductor.eval(&wo, &frame.coordinates(&direction.unit_vector()))
+            }
+            Material::RoughDielectric(dielectric) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                dielectric.eval(&wo, &wi, hit_record.facing_front)
+            }
+            Material::Principled(principled) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                principled.bsdf(hit_record).eval(&wo, &wi)
+            }
+            Material::Coated { base, .. } if !hit_record.facing_front => {
+                base.eval(ray_in, hit_record, direction)
+            }
+            Material::Coated { base, coat } => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                coat.eval(&wo, &wi)
+                    + coat.transmittance(&wo)
+                        * coat.transmittance(&wi)
+                        * base.eval(ray_in, hit_record, direction)
+            }
+            Material::Isotropic(albedo) => {
+                albedo.value(hit_record.u, hit_record.v, &hit_record.point) / (4.0 * PI)
+            }
+            Material::NormalMapped { base, .. } => base.eval(ray_in, hit_record, direction),
+            _ => Color::new_eq(0.0),
+        }
+    }
+
+    /// Density of `scatter` picking the direction, zero for specular materials
+    pub fn pdf(&self, ray_in: &Ray, hit_record: &HitRecord, direction: &Vector3) -> FloatType {
+        if self.diffuse_albedo(hit_record).is_some() {
+            return hit_record.normal.dot(&direction.unit_vector()).max(0.0) / PI;
+        }
+        match self {
+            Material::Conductor(conductor) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                conductor.pdf(&wo, &frame.coordinates(&direction.unit_vector()))
+            }
+            Material::RoughDielectric(dielectric) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                dielectric.pdf(&wo, &wi, hit_record.facing_front)
+            }
+            Material::Principled(principled) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                principled.bsdf(hit_record).pdf(&wo, &wi)
+            }
+            Material::Coated { base, .. } if !hit_record.facing_front => {
+                base.pdf(ray_in, hit_record, direction)
+            }
+            Material::Coated { base, coat } => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                let wi = frame.coordinates(&direction.unit_vector());
+                let reflectance = coat.reflectance(&wo);
+                reflectance * coat.pdf(&wo, &wi)
+                    + (1.0 - reflectance) * base.pdf(ray_in, hit_record, direction)
+            }
+            Material::Isotropic(_) => 1.0 / (4.0 * PI),
+            Material::NormalMapped { base, .. } => base.pdf(ray_in, hit_record, direction),
+            _ => 0.0,
+        }
+    }
 
-                Some((*albedo, Ray::new(hit_record.point, scatter_direction)))
+    /// Samples the direction of the scattered ray, None if the light is absorbed
+    pub fn scatter(&self, ray_in: &Ray, hit_record: &HitRecord) -> Option<ScatterRecord> {
+        match self {
+            Material::Lambertian(_) | Material::VertexColor => {
+                let direction =
+                    Onb::from_w(&hit_record.normal).local(&Vector3::random_cosine_direction());
+                let ray = Ray::new(hit_record.point, direction);
+                Some(ScatterRecord {
+                    value: self.eval(ray_in, hit_record, &direction),
+                    pdf: self.pdf(ray_in, hit_record, &direction),
+                    ray,
+                    is_specular: false,
+                })
             }
             Material::Metal(metal) => {
                 let reflected = ray_in.direction.unit_vector().reflect(hit_record.normal);
@@ -59,8 +237,12 @@ impl Material {
                     hit_record.point,
                     reflected + metal.fuzz * Vector3::random_in_unit_sphere(),
                 );
+                // Fuzzy reflections have no closed form density and are treated as specular
                 if scattered.direction.dot(&hit_record.normal) > 0.0 {
-                    Some((metal.albedo, scattered))
+                    let albedo = metal
+                        .albedo
+                        .value(hit_record.u, hit_record.v, &hit_record.point);
+                    Some(ScatterRecord::specular(scattered, albedo))
                 } else {
                     None
                 }
@@ -86,8 +268,347 @@ impl Material {
                     unit_direction.refract(hit_record.normal, refraction_ratio)
                 };
 
-                Some((attenuation, Ray::new(hit_record.point, direction)))
+                Some(ScatterRecord::specular(
+                    Ray::new(hit_record.point, direction),
+                    attenuation,
+                ))
+            }
+            Material::DiffuseLight(_) => None,
+            Material::Isotropic(_) => {
+                let direction = Vector3::random_unit_vector();
+                Some(ScatterRecord {
+                    value: self.eval(ray_in, hit_record, &direction),
+                    pdf: self.pdf(ray_in, hit_record, &direction),
+                    ray: Ray::new(hit_record.point, direction),
+                    is_specular: false,
+                })
+            }
+            Material::Conductor(conductor) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                conductor
+                    .sample(&wo)
+                    .map(|sample| sample.into_scatter_record(&frame, hit_record))
+            }
+            Material::RoughDielectric(dielectric) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                dielectric
+                    .sample(&wo, hit_record.facing_front)
+                    .map(|sample| sample.into_scatter_record(&frame, hit_record))
+            }
+            Material::Principled(principled) => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                principled
+                    .bsdf(hit_record)
+                    .sample(&wo)
+                    .map(|sample| sample.into_scatter_record(&frame, hit_record))
+            }
+            Material::Coated { base, .. } if !hit_record.facing_front => {
+                base.scatter(ray_in, hit_record)
+            }
+            Material::Coated { base, coat } => {
+                let (frame, wo) = local_frame(ray_in, hit_record);
+                // Picking the coat with its Fresnel reflectance leaves the mirror reflection of
+                // a smooth coat a weight of one
+                let reflectance = coat.reflectance(&wo);
+                let scatter = if random_float() < reflectance {
+                    coat.sample(&wo)?.into_scatter_record(&frame, hit_record)
+                } else {
+                    let scatter = base.scatter(ray_in, hit_record)?;
+                    if scatter.is_specular {
+                        let wi = frame.coordinates(&scatter.ray.direction.unit_vector());
+                        let attenuation = coat.transmittance(&wo) * coat.transmittance(&wi)
+                            / (1.0 - reflectance)
+                            * scatter.attenuation();
+                        return Some(ScatterRecord::specular(scatter.ray, attenuation));
+                    }
+                    scatter
+                };
+                if scatter.is_specular {
+                    return Some(scatter);
+                }
+                // The other layer could have picked the direction as well
+                let direction = scatter.ray.direction;
+                Some(ScatterRecord {
+                    value: self.eval(ray_in, hit_record, &direction),
+                    pdf: self.pdf(ray_in, hit_record, &direction),
+                    ray: scatter.ray,
+                    is_specular: false,
+                })
+            }
+            Material::NormalMapped { base, .. } => base.scatter(ray_in, hit_record),
+        }
+    }
+}
+
+/// Frame around the shading normal and the direction towards the origin of the ray in it
+fn local_frame(ray_in: &Ray, hit_record: &HitRecord) -> (Onb, Vector3) {
+    let frame = Onb::from_w(&hit_record.normal);
+    let wo = frame.coordinates(&-ray_in.direction.unit_vector());
+    (frame, wo)
+}
+
+impl MicrofacetSample {
+    fn into_scatter_record(self, frame: &Onb, hit_record: &HitRecord) -> ScatterRecord {
+        ScatterRecord {
+            ray: Ray::new(hit_record.point, frame.local(&self.wi)),
+            value: self.value,
+            pdf: self.pdf,
+            is_specular: self.is_specular,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{utils::reseed_rng, vec::Point3};
+
+    /// Directions towards the viewer from straight above to grazing
+    fn views() -> Vec<Vector3> {
+        [0.0 as FloatType, 30.0, 60.0, 85.0]
+            .iter()
+            .map(|angle| {
+                let angle = angle.to_radians();
+                Vector3::new(angle.sin(), 0.0, angle.cos())
+            })
+            .collect()
+    }
+
+    /// Ray from `wo` to the origin and its hit on a surface facing +z
+    fn hit<'a>(material: &'a Material, wo: &Vector3) -> (Ray, HitRecord<'a>) {
+        let ray = Ray::new(*wo, -*wo);
+        let hit_record = HitRecord::new_face_normal(
+            Point3::new_eq(0.0),
+            1.0,
+            &ray,
+            Vector3::new(0.0, 0.0, 1.0),
+            (0.5, 0.5),
+            material,
+        );
+        (ray, hit_record)
+    }
+
+    /// Share of the light from `wo` that is scattered, estimated with `scatter`
+    fn sampled_albedo(material: &Material, wo: &Vector3, count: usize) -> Color {
+        let (ray, hit_record) = hit(material, wo);
+        let mut sum = Color::new_eq(0.0);
+        for _ in 0..count {
+            if let Some(scatter) = material.scatter(&ray, &hit_record) {
+                if scatter.is_specular || scatter.pdf > 0.0 {
+                    sum += scatter.attenuation();
+                }
+            }
+        }
+        sum / count as FloatType
+    }
+
+    /// The same as `sampled_albedo`, but integrating `eval` with uniform directions
+    fn uniform_albedo(material: &Material, wo: &Vector3, count: usize) -> Color {
+        let (ray, hit_record) = hit(material, wo);
+        let mut sum = Color::new_eq(0.0);
+        for _ in 0..count {
+            let direction = Vector3::random_unit_vector();
+            sum += material.eval(&ray, &hit_record, &direction);
+        }
+        4.0 * PI * sum / count as FloatType
+    }
+
+    /// Integral of `pdf` over all directions
+    fn pdf_integral(material: &Material, wo: &Vector3, count: usize) -> FloatType {
+        let (ray, hit_record) = hit(material, wo);
+        let sum: FloatType = (0..count)
+            .map(|_| material.pdf(&ray, &hit_record, &Vector3::random_unit_vector()))
+            .sum();
+        4.0 * PI * sum / count as FloatType
+    }
+
+    /// Checks that the samples report the values `eval` and `pdf` give for their direction
+    fn check_samples(material: &Material, wo: &Vector3, count: usize) {
+        let (ray, hit_record) = hit(material, wo);
+        for _ in 0..count {
+            let scatter = match material.scatter(&ray, &hit_record) {
+                Some(scatter) if !scatter.is_specular => scatter,
+                _ => continue,
+            };
+            let direction = scatter.ray.direction;
+            let pdf = material.pdf(&ray, &hit_record, &direction);
+            let value = material.eval(&ray, &hit_record, &direction);
+            assert!(
+                (scatter.pdf - pdf).abs() <= 1.0e-3 * pdf.max(1.0),
+                "pdf {} vs {}",
+                scatter.pdf,
+                pdf
+            );
+            assert!(
+                (scatter.value - value).length() <= 1.0e-3 * value.length().max(1.0),
+                "value {} vs {}",
+                scatter.value,
+                value
+            );
+        }
+    }
+
+    fn assert_color_near(color: Color, expected: Color, tolerance: FloatType) {
+        let difference = color - expected;
+        assert!(
+            (0..3).all(|axis| difference[axis].abs() <= tolerance),
+            "{} vs {}",
+            color,
+            expected
+        );
+    }
+
+    #[test]
+    fn lambertian_white_furnace() {
+        reseed_rng(1);
+        let albedo = Color::new(0.9, 0.5, 0.1);
+        let material = Material::Lambertian(albedo.into());
+        for wo in views() {
+            assert_color_near(sampled_albedo(&material, &wo, 10_000), albedo, 1.0e-3);
+            assert_color_near(uniform_albedo(&material, &wo, 100_000), albedo, 0.02);
+            check_samples(&material, &wo, 1_000);
+        }
+
+        let white = Material::Lambertian(Color::new_eq(1.0).into());
+        assert_color_near(
+            uniform_albedo(&white, &views()[1], 100_000),
+            Color::new_eq(1.0),
+            0.02,
+        );
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        reseed_rng(2);
+        let materials = [
+            Material::Lambertian(Color::new_eq(0.5).into()),
+            Material::VertexColor,
+            Material::Isotropic(Color::new_eq(0.5).into()),
+        ];
+        for material in materials.iter() {
+            for wo in views() {
+                let integral = pdf_integral(material, &wo, 100_000);
+                assert!(
+                    (integral - 1.0).abs() < 0.02,
+                    "pdf integrates to {}",
+                    integral
+                );
+            }
+        }
+    }
+
+    fn coated(base: Material, index_of_refraction: FloatType, roughness: FloatType) -> Material {
+        Material::Coated {
+            base: Box::new(base),
+            coat: Coat::new(index_of_refraction, roughness, 0.0, Color::new_eq(0.0)),
+        }
+    }
+
+    /// A diffuse, a metallic and a layered base
+    fn bases() -> Vec<Material> {
+        vec![
+            Material::Lambertian(Color::new(0.9, 0.5, 0.1).into()),
+            Material::Conductor(Conductor::new(
+                Color::new(0.14, 0.37, 1.44),
+                Color::new(3.98, 2.39, 1.6),
+                0.4,
+            )),
+            Material::Principled(Principled::default()),
+        ]
+    }
+
+    #[test]
+    fn invisible_coat_matches_base() {
+        // Without a change of the refractive index and without thickness the coat neither
+        // reflects nor absorbs anything
+        reseed_rng(3);
+        for base in bases() {
+            for roughness in [0.0, 0.3] {
+                let material = coated(base.clone(), 1.0, roughness);
+                for wo in views() {
+                    let (ray, hit_record) = hit(&material, &wo);
+                    for _ in 0..100 {
+                        let direction = Vector3::random_unit_vector();
+                        let pdf = material.pdf(&ray, &hit_record, &direction);
+                        let base_pdf = base.pdf(&ray, &hit_record, &direction);
+                        assert!((pdf - base_pdf).abs() <= 1.0e-5 * base_pdf.max(1.0));
+                        assert_color_near(
+                            material.eval(&ray, &hit_record, &direction),
+                            base.eval(&ray, &hit_record, &direction),
+                            1.0e-5,
+                        );
+                    }
+                    check_samples(&material, &wo, 1_000);
+                    assert_color_near(
+                        sampled_albedo(&material, &wo, 10_000),
+                        sampled_albedo(&base, &wo, 10_000),
+                        0.02,
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coat_thickness_scales_absorption() {
+        reseed_rng(4);
+        let base = Material::Lambertian(Color::new_eq(0.8).into());
+        let absorption = Color::new(2.0, 0.5, 0.0);
+        let coat = |thickness| Material::Coated {
+            base: Box::new(base.clone()),
+            coat: Coat::new(1.5, 0.0, thickness, absorption),
+        };
+        let clear = coated(base.clone(), 1.5, 0.0);
+        let (thin, thick) = (coat(0.0), coat(0.5));
+        for wo in views() {
+            let (ray, hit_record) = hit(&clear, &wo);
+            let direction = Vector3::new(0.3, -0.2, 0.9);
+            let expected = clear.eval(&ray, &hit_record, &direction);
+            assert_color_near(thin.eval(&ray, &hit_record, &direction), expected, 1.0e-6);
+            // Absorbed along the longer refracted paths in and out
+            let tinted = thick.eval(&ray, &hit_record, &direction);
+            assert!(tinted.x < tinted.y && tinted.y < tinted.z, "{}", tinted);
+            assert_eq!(tinted.z, expected.z);
+        }
+    }
+
+    #[test]
+    fn coated_conserves_energy() {
+        reseed_rng(5);
+        let white_bases = [
+            Material::Lambertian(Color::new_eq(1.0).into()),
+            // Fresnel reflectance of one
+            Material::Conductor(Conductor::new(
+                Color::new_eq(0.0),
+                Color::new_eq(1.0e4),
+                0.3,
+            )),
+        ];
+        for base in white_bases.iter() {
+            for roughness in [0.0, 0.1, 0.5, 1.0] {
+                let material = coated(base.clone(), 1.5, roughness);
+                for wo in views() {
+                    let albedo = sampled_albedo(&material, &wo, 10_000);
+                    assert!(
+                        (0..3).all(|axis| albedo[axis] <= 1.01),
+                        "coat with roughness {} reflects {} from {}",
+                        roughness,
+                        albedo,
+                        wo
+                    );
+                }
             }
         }
+
+        // The rough coat and the base pick directions the other one evaluates
+        let material = coated(Material::Lambertian(Color::new_eq(1.0).into()), 1.5, 0.5);
+        for wo in views().iter().take(3) {
+            check_samples(&material, wo, 1_000);
+            assert_color_near(
+                sampled_albedo(&material, wo, 20_000),
+                uniform_albedo(&material, wo, 100_000),
+                0.03,
+            );
+        }
     }
 }
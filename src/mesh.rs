@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::Aabb,
+    hittable::{AreaLight, HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    triangle,
+    vec::{Color, FloatType, Point3, Vector3},
+};
+
+/// Indexed triangle mesh
+///
+/// All vertex attributes share the same index, `normals`, `uvs` and `colors` are either
+/// empty or have one entry per position.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vector3>,
+    pub uvs: Vec<(FloatType, FloatType)>,
+    /// Linear vertex colors, used as albedo by `Material::VertexColor`
+    pub colors: Vec<Color>,
+    /// Counter-clockwise vertex indices of every triangle
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+}
+
+impl TriangleMesh {
+    /// Panics if an index or attribute buffer doesn't match the positions
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vector3>,
+        uvs: Vec<(FloatType, FloatType)>,
+        colors: Vec<Color>,
+        indices: Vec<[usize; 3]>,
+        material: Material,
+    ) -> Self {
+        assert!(
+            normals.is_empty() || normals.len() == positions.len(),
+            "Mesh needs one normal per vertex"
+        );
+        assert!(
+            uvs.is_empty() || uvs.len() == positions.len(),
+            "Mesh needs one uv per vertex"
+        );
+        assert!(
+            colors.is_empty() || colors.len() == positions.len(),
+            "Mesh needs one color per vertex"
+        );
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "Mesh index out of range"
+        );
+        Self {
+            positions,
+            normals,
+            uvs,
+            colors,
+            indices,
+            material,
+        }
+    }
+
+    /// Splits the mesh into one hittable per triangle, all sharing the vertex buffers
+    ///
+    /// Adding the triangles individually lets the BVH of the scene sort them.
+    pub fn into_triangles(self) -> Vec<MeshTriangle> {
+        let mesh = Arc::new(self);
+        (0..mesh.indices.len())
+            .map(|index| MeshTriangle {
+                mesh: Arc::clone(&mesh),
+                index,
+            })
+            .collect()
+    }
+
+    fn vertices(&self, index: usize) -> [Point3; 3] {
+        let [a, b, c] = self.indices[index];
+        [self.positions[a], self.positions[b], self.positions[c]]
+    }
+}
+
+/// One triangle of a `TriangleMesh`
+pub struct MeshTriangle {
+    mesh: Arc<TriangleMesh>,
+    index: usize,
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
+        let mesh = &*self.mesh;
+        let vertices = mesh.vertices(self.index);
+        let (t, [b0, b1, b2]) = triangle::intersect(r, &vertices, t_min, t_max)?;
+        let [i0, i1, i2] = mesh.indices[self.index];
+
+        let [a, b, c] = vertices;
+        let outward_normal = (b - a).cross(&(c - a)).unit_vector();
+        let (uv, dpdu, dpdv) = if mesh.uvs.is_empty() {
+            ((b1, b2), b - a, c - a)
+        } else {
+            let (uv0, uv1, uv2) = (mesh.uvs[i0], mesh.uvs[i1], mesh.uvs[i2]);
+            let uv = (
+                b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+                b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+            );
+            // Solves the edges for the derivatives, degenerate mappings have none
+            let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+            let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+            let determinant = du1 * dv2 - dv1 * du2;
+            if determinant.abs() < 1.0e-12 {
+                (uv, Vector3::new_eq(0.0), Vector3::new_eq(0.0))
+            } else {
+                let (dp1, dp2) = (b - a, c - a);
+                (
+                    uv,
+                    (dv2 * dp1 - dv1 * dp2) / determinant,
+                    (du1 * dp2 - du2 * dp1) / determinant,
+                )
+            }
+        };
+
+        let mut record =
+            HitRecord::new_face_normal(r.at(t), t, r, outward_normal, uv, &mesh.material);
+        record.dpdu = dpdu;
+        record.dpdv = dpdv;
+        if !mesh.normals.is_empty() {
+            let shading_normal =
+                b0 * mesh.normals[i0] + b1 * mesh.normals[i1] + b2 * mesh.normals[i2];
+            if !shading_normal.is_near_zero() {
+                record.set_shading_normal(shading_normal.unit_vector());
+            }
+        }
+        if !mesh.colors.is_empty() {
+            record.color = Some(b0 * mesh.colors[i0] + b1 * mesh.colors[i1] + b2 * mesh.colors[i2]);
+        }
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle::triangle_box(&self.mesh.vertices(self.index)))
+    }
+
+    fn as_light(&self) -> Option<&dyn AreaLight> {
+        if self.mesh.material.is_emissive() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl AreaLight for MeshTriangle {
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> FloatType {
+        triangle::pdf_towards(&self.mesh.vertices(self.index), origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        triangle::random_towards(&self.mesh.vertices(self.index), origin)
+    }
+}
@@ -0,0 +1,662 @@
+//! Microfacet BSDFs with the Trowbridge-Reitz (GGX) distribution
+//!
+//! All directions are in a local frame with the shading normal along +z and point away from
+//! the surface, `wo` towards the viewer and `wi` towards the light.
+use crate::{
+    utils::random_float,
+    vec::{Color, FloatType, Vector3, PI},
+};
+
+/// Below this alpha surfaces are treated as perfectly smooth
+const SMOOTH_ALPHA: FloatType = 1.0e-3;
+
+/// Trowbridge-Reitz (GGX) distribution of microfacet normals with Smith masking
+#[derive(Clone, Copy)]
+pub struct TrowbridgeReitz {
+    alpha: FloatType,
+}
+
+impl TrowbridgeReitz {
+    /// Roughness in [0,1] is squared to get alpha, which makes it perceptually more linear
+    pub fn new(roughness: FloatType) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        Self {
+            alpha: roughness * roughness,
+        }
+    }
+
+    /// Smooth surfaces only reflect and refract into the mirror directions
+    pub fn is_smooth(&self) -> bool {
+        self.alpha < SMOOTH_ALPHA
+    }
+
+    /// Density of microfacet normals per projected area
+    pub fn d(&self, wm: &Vector3) -> FloatType {
+        let cos2 = wm.z * wm.z;
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let e = (1.0 - cos2) / cos2 / alpha2;
+        1.0 / (PI * alpha2 * cos2 * cos2 * (1.0 + e) * (1.0 + e))
+    }
+
+    /// Smith's auxiliary function of the hidden microfacet area
+    fn lambda(&self, w: &Vector3) -> FloatType {
+        let cos2 = w.z * w.z;
+        if cos2 <= 0.0 {
+            return FloatType::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+
+    /// Fraction of the microfacets visible from `w`
+    pub fn g1(&self, w: &Vector3) -> FloatType {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of the microfacets visible from both directions (height correlated)
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> FloatType {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Density of the normals visible from `wo`, zero for those facing away from it
+    pub fn visible_pdf(&self, wo: &Vector3, wm: &Vector3) -> FloatType {
+        if wo.z == 0.0 {
+            return 0.0;
+        }
+        let cosine = (wo.dot(wm) * wo.z.signum()).max(0.0);
+        self.g1(wo) / wo.z.abs() * self.d(wm) * cosine
+    }
+
+    /// Samples a normal visible from `wo` (Heitz 2018, "Sampling the GGX Distribution of
+    /// Visible Normals")
+    pub fn sample_visible_normal(&self, wo: &Vector3) -> Vector3 {
+        let wo = if wo.z < 0.0 { -*wo } else { *wo };
+        // Stretch into the configuration of a hemisphere with alpha 1
+        let wh = Vector3::new(self.alpha * wo.x, self.alpha * wo.y, wo.z).unit_vector();
+        let length_squared = wh.x * wh.x + wh.y * wh.y;
+        let t1 = if length_squared > 0.0 {
+            Vector3::new(-wh.y, wh.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+
+        // Uniform point on the disk, warped onto the visible half
+        let radius = random_float().sqrt();
+        let phi = 2.0 * PI * random_float();
+        let p1 = radius * phi.cos();
+        let s = 0.5 * (1.0 + wh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * radius * phi.sin();
+        let p3 = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let nh = p1 * t1 + p2 * t2 + p3 * wh;
+
+        Vector3::new(self.alpha * nh.x, self.alpha * nh.y, nh.z.max(1.0e-6)).unit_vector()
+    }
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, `eta` is the ratio of the
+/// refractive index below the surface to the one above
+pub fn fresnel_dielectric(cos_theta: FloatType, eta: FloatType) -> FloatType {
+    let (cos_i, eta) = if cos_theta < 0.0 {
+        (-cos_theta.max(-1.0), 1.0 / eta)
+    } else {
+        (cos_theta.min(1.0), eta)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+/// Unpolarized Fresnel reflectance of a conductor with complex refractive index `eta + i k`
+/// per color channel, seen from vacuum
+pub fn fresnel_conductor(cos_theta: FloatType, eta: &Color, k: &Color) -> Color {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let channel = |eta: FloatType, k: FloatType| {
+        let cos2 = cos_i * cos_i;
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_i * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    Color::new(
+        channel(eta.x, k.x),
+        channel(eta.y, k.y),
+        channel(eta.z, k.z),
+    )
+}
+
+/// Mirror direction of `w` around `normal`, both pointing away from the surface
+pub fn reflect(w: &Vector3, normal: &Vector3) -> Vector3 {
+    -*w + 2.0 * w.dot(normal) * *normal
+}
+
+/// Refracted direction of `w` through the surface with `normal`, None on total internal
+/// reflection. `eta` is the ratio of the refractive indices on the far and near side of the
+/// normal, the result points away from the surface on the far side.
+pub fn refract(w: &Vector3, normal: &Vector3, eta: FloatType) -> Option<Vector3> {
+    let (cos_i, eta, normal) = if w.dot(normal) < 0.0 {
+        (-w.dot(normal), 1.0 / eta, -*normal)
+    } else {
+        (w.dot(normal), eta, *normal)
+    };
+    let sin2_t = (1.0 - cos_i * cos_i).max(0.0) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some(-*w / eta + (cos_i / eta - cos_t) * normal)
+}
+
+/// Complex refractive indices (eta, k) of common metals at red, green and blue wavelengths
+const METALS: [(&str, [FloatType; 3], [FloatType; 3]); 5] = [
+    ("aluminium", [1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+    ("copper", [0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+    ("gold", [0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+    ("iron", [2.868, 2.918, 2.652], [3.130, 2.930, 2.802]),
+    ("silver", [0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+];
+
+/// Names accepted by `named_metal`
+pub fn metal_names() -> impl Iterator<Item = &'static str> {
+    METALS.iter().map(|(name, _, _)| *name)
+}
+
+/// Refractive index and extinction coefficient of a metal by name
+pub fn named_metal(name: &str) -> Option<(Color, Color)> {
+    METALS
+        .iter()
+        .find(|(metal, _, _)| *metal == name)
+        .map(|(_, eta, k)| {
+            (
+                Color::new(eta[0], eta[1], eta[2]),
+                Color::new(k[0], k[1], k[2]),
+            )
+        })
+}
+
+/// Result of sampling a microfacet BSDF, in the local frame
+pub struct MicrofacetSample {
+    pub wi: Vector3,
+    /// BSDF times the absolute cosine of `wi`
+    pub value: Color,
+    pub pdf: FloatType,
+    /// Sampled from the mirror directions of a smooth surface
+    pub is_specular: bool,
+}
+
+/// Metal with a complex refractive index per color channel
+#[derive(Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: FloatType) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        if self.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new_eq(0.0);
+        }
+        let wm = (*wo + *wi).unit_vector();
+        let fresnel = fresnel_conductor(wo.dot(&wm), &self.eta, &self.k);
+        self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z) * fresnel
+    }
+
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3) -> FloatType {
+        if self.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (*wo + *wi).unit_vector();
+        self.distribution.visible_pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    pub fn sample(&self, wo: &Vector3) -> Option<MicrofacetSample> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.is_smooth() {
+            return Some(MicrofacetSample {
+                wi: Vector3::new(-wo.x, -wo.y, wo.z),
+                value: fresnel_conductor(wo.z, &self.eta, &self.k),
+                pdf: 1.0,
+                is_specular: true,
+            });
+        }
+        let wm = self.distribution.sample_visible_normal(wo);
+        let wi = reflect(wo, &wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(MicrofacetSample {
+            wi,
+            value: self.eval(wo, &wi),
+            pdf: self.pdf(wo, &wi),
+            is_specular: false,
+        })
+    }
+}
+
+/// Glass with a rough surface, reflects and refracts into a spread of directions
+#[derive(Clone)]
+pub struct RoughDielectric {
+    index_of_refraction: FloatType,
+    distribution: TrowbridgeReitz,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: FloatType, roughness: FloatType) -> Self {
+        Self {
+            index_of_refraction,
+            distribution: TrowbridgeReitz::new(roughness),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    /// Ratio of the refractive indices behind and in front of the surface seen from `wo`
+    fn eta(&self, facing_front: bool) -> FloatType {
+        if facing_front {
+            self.index_of_refraction
+        } else {
+            1.0 / self.index_of_refraction
+        }
+    }
+
+    /// Half vector of the directions and the relative refractive index along them
+    fn generalized_half_vector(
+        &self,
+        wo: &Vector3,
+        wi: &Vector3,
+        facing_front: bool,
+    ) -> Option<(Vector3, FloatType)> {
+        let reflect = wo.z * wi.z > 0.0;
+        let eta = if reflect { 1.0 } else { self.eta(facing_front) };
+        let wm = eta * *wi + *wo;
+        if wi.z == 0.0 || wo.z == 0.0 || wm.is_near_zero() {
+            return None;
+        }
+        let wm = wm.unit_vector();
+        let wm = if wm.z < 0.0 { -wm } else { wm };
+        // Microfacets facing away from either direction don't contribute
+        if wm.dot(wi) * wi.z < 0.0 || wm.dot(wo) * wo.z < 0.0 {
+            return None;
+        }
+        Some((wm, eta))
+    }
+
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3, facing_front: bool) -> Color {
+        if self.is_smooth() {
+            return Color::new_eq(0.0);
+        }
+        let (wm, eta) = match self.generalized_half_vector(wo, wi, facing_front) {
+            Some(half_vector) => half_vector,
+            None => return Color::new_eq(0.0),
+        };
+        let fresnel = fresnel_dielectric(wo.dot(&wm), self.eta(facing_front));
+        let d = self.distribution.d(&wm);
+        let g = self.distribution.g(wo, wi);
+        let value = if eta == 1.0 {
+            d * g * fresnel / (4.0 * wo.z.abs())
+        } else {
+            let denominator = wi.dot(&wm) + wo.dot(&wm) / eta;
+            // Radiance is compressed into the smaller solid angle of the denser medium
+            d * (1.0 - fresnel) * g * (wi.dot(&wm) * wo.dot(&wm)).abs()
+                / (wo.z.abs() * denominator * denominator)
+                / (eta * eta)
+        };
+        Color::new_eq(value)
+    }
+
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3, facing_front: bool) -> FloatType {
+        if self.is_smooth() {
+            return 0.0;
+        }
+        let (wm, eta) = match self.generalized_half_vector(wo, wi, facing_front) {
+            Some(half_vector) => half_vector,
+            None => return 0.0,
+        };
+        let reflectance = fresnel_dielectric(wo.dot(&wm), self.eta(facing_front));
+        let visible_pdf = self.distribution.visible_pdf(wo, &wm);
+        if eta == 1.0 {
+            visible_pdf / (4.0 * wo.dot(&wm).abs()) * reflectance
+        } else {
+            let denominator = wi.dot(&wm) + wo.dot(&wm) / eta;
+            visible_pdf * wi.dot(&wm).abs() / (denominator * denominator) * (1.0 - reflectance)
+        }
+    }
+
+    pub fn sample(&self, wo: &Vector3, facing_front: bool) -> Option<MicrofacetSample> {
+        let eta = self.eta(facing_front);
+        if self.is_smooth() {
+            let normal = Vector3::new(0.0, 0.0, 1.0);
+            let reflectance = fresnel_dielectric(wo.z, eta);
+            // Picking reflection with the Fresnel probability leaves a weight of one
+            return match refract(wo, &normal, eta) {
+                Some(wi) if random_float() >= reflectance => Some(MicrofacetSample {
+                    wi,
+                    value: Color::new_eq(1.0 / (eta * eta)),
+                    pdf: 1.0,
+                    is_specular: true,
+                }),
+                _ => Some(MicrofacetSample {
+                    wi: reflect(wo, &normal),
+                    value: Color::new_eq(1.0),
+                    pdf: 1.0,
+                    is_specular: true,
+                }),
+            };
+        }
+
+        let wm = self.distribution.sample_visible_normal(wo);
+        let reflectance = fresnel_dielectric(wo.dot(&wm), eta);
+        let wi = if random_float() < reflectance {
+            let wi = reflect(wo, &wm);
+            if wo.z * wi.z <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract(wo, &wm, eta)?;
+            if wo.z * wi.z >= 0.0 {
+                return None;
+            }
+            wi
+        };
+        let pdf = self.pdf(wo, &wi, facing_front);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(MicrofacetSample {
+            wi,
+            value: self.eval(wo, &wi, facing_front),
+            pdf,
+            is_specular: false,
+        })
+    }
+}
+
+/// Clear dielectric layer over another material. Light reaching the layer below loses what
+/// the coat reflects and what it absorbs along the refracted paths in and out.
+#[derive(Clone)]
+pub struct Coat {
+    index_of_refraction: FloatType,
+    distribution: TrowbridgeReitz,
+    /// Absorption coefficient times thickness, the optical depth at normal incidence
+    optical_depth: Color,
+}
+
+impl Coat {
+    pub fn new(
+        index_of_refraction: FloatType,
+        roughness: FloatType,
+        thickness: FloatType,
+        absorption: Color,
+    ) -> Self {
+        Self {
+            index_of_refraction,
+            distribution: TrowbridgeReitz::new(roughness),
+            optical_depth: thickness * absorption,
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.distribution.is_smooth()
+    }
+
+    /// Fresnel reflectance of the coat for light arriving along `w`
+    pub fn reflectance(&self, w: &Vector3) -> FloatType {
+        fresnel_dielectric(w.z.abs(), self.index_of_refraction)
+    }
+
+    /// Share of the light crossing the coat along `w` in either direction, one for
+    /// directions below the surface which leave through the base
+    pub fn transmittance(&self, w: &Vector3) -> Color {
+        if w.z <= 0.0 {
+            return Color::new_eq(1.0);
+        }
+        let sin2_t = (1.0 - w.z * w.z) / (self.index_of_refraction * self.index_of_refraction);
+        let cos_t = (1.0 - sin2_t).max(1.0e-4).sqrt();
+        let absorbed = -self.optical_depth / cos_t;
+        (1.0 - self.reflectance(w))
+            * Color::new(absorbed.x.exp(), absorbed.y.exp(), absorbed.z.exp())
+    }
+
+    /// Reflection of a rough coat, zero for smooth ones
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        if self.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new_eq(0.0);
+        }
+        let wm = (*wo + *wi).unit_vector();
+        let fresnel = fresnel_dielectric(wo.dot(&wm), self.index_of_refraction);
+        Color::new_eq(
+            self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z) * fresnel,
+        )
+    }
+
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3) -> FloatType {
+        if self.is_smooth() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let wm = (*wo + *wi).unit_vector();
+        self.distribution.visible_pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+    }
+
+    /// Samples the reflection off the coat, the mirror direction with weight one if smooth
+    pub fn sample(&self, wo: &Vector3) -> Option<MicrofacetSample> {
+        if self.is_smooth() {
+            return Some(MicrofacetSample {
+                wi: Vector3::new(-wo.x, -wo.y, wo.z),
+                value: Color::new_eq(1.0),
+                pdf: 1.0,
+                is_specular: true,
+            });
+        }
+        let wi = reflect(wo, &self.distribution.sample_visible_normal(wo));
+        if wi.z <= 0.0 {
+            return None;
+        }
+        Some(MicrofacetSample {
+            wi,
+            value: self.eval(wo, &wi),
+            pdf: self.pdf(wo, &wi),
+            is_specular: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::reseed_rng;
+
+    const ROUGHNESSES: [FloatType; 6] = [0.05, 0.1, 0.2, 0.5, 0.8, 1.0];
+
+    /// Directions towards the viewer at 0, 30, 60 and 85 degrees from the normal
+    fn views() -> Vec<Vector3> {
+        [0.0 as FloatType, 30.0, 60.0, 85.0]
+            .iter()
+            .map(|degrees| {
+                let (sin, cos) = degrees.to_radians().sin_cos();
+                Vector3::new(sin, 0.0, cos)
+            })
+            .collect()
+    }
+
+    /// Metal with a Fresnel reflectance of one at every angle
+    fn perfect_conductor(roughness: FloatType) -> Conductor {
+        Conductor::new(Color::new_eq(0.0), Color::new_eq(1.0e4), roughness)
+    }
+
+    /// Mean of the sample weights, the share of the light from `wo` that is scattered
+    fn sampled_albedo(sample: impl Fn() -> Option<MicrofacetSample>, count: usize) -> FloatType {
+        let sum: FloatType = (0..count)
+            .filter_map(|_| sample())
+            .map(|sample| sample.value.x / sample.pdf)
+            .sum();
+        sum / count as FloatType
+    }
+
+    fn uniform_hemisphere() -> Vector3 {
+        let w = Vector3::random_unit_vector();
+        Vector3::new(w.x, w.y, w.z.abs())
+    }
+
+    #[test]
+    fn visible_normals_integrate_to_one() {
+        // The projected area of the visible microfacets equals that of the surface
+        reseed_rng(1);
+        let count = 50_000;
+        for roughness in [0.5, 0.8, 1.0] {
+            let distribution = TrowbridgeReitz::new(roughness);
+            for wo in views() {
+                let sum: FloatType = (0..count)
+                    .map(|_| distribution.visible_pdf(&wo, &uniform_hemisphere()))
+                    .sum();
+                let integral = 2.0 * PI * sum / count as FloatType;
+                assert!(
+                    (integral - 1.0).abs() < 0.03,
+                    "{} at {}",
+                    integral,
+                    roughness
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn conductor_white_furnace() {
+        reseed_rng(2);
+        for roughness in ROUGHNESSES {
+            let conductor = perfect_conductor(roughness);
+            for wo in views() {
+                let albedo = sampled_albedo(|| conductor.sample(&wo), 20_000);
+                assert!(albedo <= 1.0 + 1e-4, "{} at {}", albedo, roughness);
+                // Single scattering misses the light bouncing between microfacets, which
+                // only becomes noticeable on rougher surfaces
+                if roughness <= 0.2 && wo.z > 0.5 {
+                    assert!(albedo > 0.99, "{} at {}", albedo, roughness);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn roughest_conductor_matches_analytic_albedo() {
+        // With alpha one D is 1/pi and G is 2 cos/(1 + cos) seen from the normal, which
+        // integrates to 1 - ln 2
+        reseed_rng(3);
+        let conductor = perfect_conductor(1.0);
+        let wo = Vector3::new(0.0, 0.0, 1.0);
+        let albedo = sampled_albedo(|| conductor.sample(&wo), 100_000);
+        assert!(
+            (albedo - (1.0 - (2.0 as FloatType).ln())).abs() < 0.005,
+            "{}",
+            albedo
+        );
+    }
+
+    #[test]
+    fn conductor_samples_match_eval_and_pdf() {
+        // Importance sampled and uniform estimates of the albedo agree, and every sample
+        // reports what eval and pdf give for its direction
+        reseed_rng(4);
+        for roughness in [0.5, 1.0] {
+            let conductor = Conductor::new(
+                Color::new(0.2, 0.9, 1.1),
+                Color::new(3.9, 2.5, 2.1),
+                roughness,
+            );
+            for wo in views() {
+                for _ in 0..1000 {
+                    let sample = match conductor.sample(&wo) {
+                        Some(sample) => sample,
+                        None => continue,
+                    };
+                    let pdf = conductor.pdf(&wo, &sample.wi);
+                    assert!((sample.pdf - pdf).abs() <= 1e-3 * pdf);
+                    assert_eq!(sample.value.y, conductor.eval(&wo, &sample.wi).y);
+                }
+
+                let sampled = sampled_albedo(|| conductor.sample(&wo), 20_000);
+                let count = 100_000;
+                let uniform = 2.0
+                    * PI
+                    * (0..count)
+                        .map(|_| conductor.eval(&wo, &uniform_hemisphere()).x)
+                        .sum::<FloatType>()
+                    / count as FloatType;
+                assert!(
+                    (sampled - uniform).abs() < 0.03,
+                    "{} vs {}",
+                    sampled,
+                    uniform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_conserves_energy() {
+        // Reflected and refracted light together, entering glass from outside
+        reseed_rng(5);
+        for roughness in ROUGHNESSES {
+            let glass = RoughDielectric::new(1.5, roughness);
+            for wo in views() {
+                let albedo = sampled_albedo(|| glass.sample(&wo, true), 20_000);
+                assert!(albedo <= 1.0 + 1e-4, "{} at {}", albedo, roughness);
+                // Refraction compresses the radiance by eta squared
+                if roughness <= 0.2 && wo.z > 0.5 {
+                    assert!(albedo > 1.0 / 2.25, "{} at {}", albedo, roughness);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rough_dielectric_samples_match_pdf() {
+        reseed_rng(6);
+        for facing_front in [true, false] {
+            let glass = RoughDielectric::new(1.5, 0.5);
+            for wo in views() {
+                for _ in 0..1000 {
+                    if let Some(sample) = glass.sample(&wo, facing_front) {
+                        let pdf = glass.pdf(&wo, &sample.wi, facing_front);
+                        assert!((sample.pdf - pdf).abs() <= 1e-3 * pdf);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+
+use crate::{
+    hittable::HitRecord,
+    onb::Onb,
+    texture::Texture,
+    vec::{FloatType, Vector3},
+};
+
+/// Step along the texture coordinates for the finite differences of bump maps
+const BUMP_DELTA: FloatType = 0.0005;
+
+/// Surface detail that tilts the shading normal without changing the geometry
+pub enum NormalMap {
+    /// Tangent space normals encoded as colors, x along u (red), y along v (green) and z
+    /// (blue) away from the surface. The strength scales the tilt.
+    Tangent {
+        normals: Arc<dyn Texture>,
+        strength: FloatType,
+    },
+    /// Height field, the mean of the color channels times the scale is the offset along the
+    /// normal in world units
+    Bump {
+        height: Arc<dyn Texture>,
+        scale: FloatType,
+    },
+}
+
+impl NormalMap {
+    /// Shading normal at the hit, on the same side as its current normal
+    pub fn perturb(&self, hit_record: &HitRecord) -> Vector3 {
+        let normal = hit_record.normal;
+        let (dpdu, dpdv) = tangents(hit_record);
+        let perturbed = match self {
+            NormalMap::Tangent { normals, strength } => {
+                let color = normals.value(hit_record.u, hit_record.v, &hit_record.point);
+                let tangent = (dpdu - normal.dot(&dpdu) * normal).unit_vector();
+                // Mirrored texture coordinates flip the bitangent
+                let mut bitangent = normal.cross(&tangent);
+                if bitangent.dot(&dpdv) < 0.0 {
+                    bitangent = -bitangent;
+                }
+                let x = strength * (2.0 * color.x - 1.0);
+                let y = strength * (2.0 * color.y - 1.0);
+                let z = (2.0 * color.z - 1.0).max(0.0);
+                x * tangent + y * bitangent + z * normal
+            }
+            NormalMap::Bump { height, scale } => {
+                let (u, v, point) = (hit_record.u, hit_record.v, hit_record.point);
+                let displacement = |u: FloatType, v: FloatType, point| {
+                    let color = height.value(u, v, &point);
+                    scale * (color.x + color.y + color.z) / 3.0
+                };
+                let base = displacement(u, v, point);
+                let du = (displacement(u + BUMP_DELTA, v, point + BUMP_DELTA * dpdu) - base)
+                    / BUMP_DELTA;
+                let dv = (displacement(u, v + BUMP_DELTA, point + BUMP_DELTA * dpdv) - base)
+                    / BUMP_DELTA;
+                // Derivatives of the displaced surface, the change of the normal is negligible
+                let displaced_dpdu = dpdu + du * normal;
+                let displaced_dpdv = dpdv + dv * normal;
+                let perturbed = displaced_dpdu.cross(&displaced_dpdv);
+                if perturbed.dot(&normal) < 0.0 {
+                    -perturbed
+                } else {
+                    perturbed
+                }
+            }
+        };
+        if perturbed.is_near_zero() {
+            normal
+        } else {
+            perturbed.unit_vector()
+        }
+    }
+}
+
+/// Surface derivatives of the hit, an arbitrary frame around the normal if it has none
+fn tangents(hit_record: &HitRecord) -> (Vector3, Vector3) {
+    let (dpdu, dpdv) = (hit_record.dpdu, hit_record.dpdv);
+    let normal = hit_record.normal;
+    if dpdu.cross(&dpdv).is_near_zero() || dpdu.cross(&normal).is_near_zero() {
+        let onb = Onb::from_w(&normal);
+        (
+            onb.local(&Vector3::new(1.0, 0.0, 0.0)),
+            onb.local(&Vector3::new(0.0, 1.0, 0.0)),
+        )
+    } else {
+        (dpdu, dpdv)
+    }
+}
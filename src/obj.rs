@@ -0,0 +1,663 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    material::{Material, Metal},
+    mesh::TriangleMesh,
+    vec::{Color, FloatType, Point3, Vector3},
+};
+
+/// Error while reading an OBJ or MTL file
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Malformed line, line numbers start at 1
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Problem that didn't stop loading, line numbers start at 1
+#[derive(Debug)]
+pub struct ObjWarning {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+/// Contents of an OBJ file
+pub struct ObjModel {
+    /// One mesh per group (`g`/`o`) and material (`usemtl`)
+    pub meshes: Vec<TriangleMesh>,
+    pub warnings: Vec<ObjWarning>,
+}
+
+/// Contents of an MTL file
+pub struct MtlLibrary {
+    pub materials: HashMap<String, Material>,
+    pub warnings: Vec<ObjWarning>,
+}
+
+/// Reads a Wavefront OBJ file and the MTL libraries it references
+///
+/// Polygons are triangulated as fans. Faces without a `usemtl` get `default_material`, so
+/// do faces with an unknown one or one from a missing MTL file, which is reported as a
+/// warning. Unsupported keywords are ignored with a warning.
+pub fn load_obj(path: impl AsRef<Path>, default_material: &Material) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    parse_obj(path, &source, default_material)
+}
+
+/// Parses the contents of an OBJ file, MTL libraries are relative to `path`
+fn parse_obj(path: &Path, source: &str, default_material: &Material) -> Result<ObjModel, ObjError> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vector3> = Vec::new();
+    let mut uvs: Vec<(FloatType, FloatType)> = Vec::new();
+    let mut materials: HashMap<String, Material> = HashMap::new();
+
+    let mut meshes = Vec::new();
+    let mut warnings = Vec::new();
+    let mut unknown_keywords = HashSet::new();
+    let mut builder = GroupBuilder::new(default_material.clone());
+
+    for (line_number, line) in logical_lines(source) {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+        let warning = |message: String| ObjWarning {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vector(&arguments, 3, 4).map_err(error)?),
+            "vn" => normals.push(parse_vector(&arguments, 3, 3).map_err(error)?),
+            "vt" => {
+                let uv = parse_floats(&arguments, 1, 3).map_err(error)?;
+                uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        arguments.len()
+                    )));
+                }
+                let mut face = Vec::with_capacity(arguments.len());
+                for vertex in arguments.iter() {
+                    let key =
+                        parse_face_vertex(vertex, (positions.len(), uvs.len(), normals.len()))
+                            .map_err(error)?;
+                    face.push(builder.vertex(key, &positions, &uvs, &normals));
+                }
+                for i in 1..face.len() - 1 {
+                    builder.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            "g" | "o" => {
+                let material = builder.material.clone();
+                meshes
+                    .extend(std::mem::replace(&mut builder, GroupBuilder::new(material)).finish());
+            }
+            "usemtl" => {
+                let name = arguments.join(" ");
+                let material = match materials.get(&name) {
+                    Some(material) => material.clone(),
+                    None => {
+                        warnings.push(warning(format!(
+                            "unknown material '{}', using the default material",
+                            name
+                        )));
+                        default_material.clone()
+                    }
+                };
+                meshes
+                    .extend(std::mem::replace(&mut builder, GroupBuilder::new(material)).finish());
+            }
+            "mtllib" => {
+                if arguments.is_empty() {
+                    return Err(error("mtllib without file name".to_string()));
+                }
+                for library in arguments.iter() {
+                    // Faces then get the default material like with an unknown usemtl
+                    match load_mtl(&directory.join(library)) {
+                        Ok(library) => {
+                            materials.extend(library.materials);
+                            warnings.extend(library.warnings);
+                        }
+                        Err(ObjError::Io { path, source }) => warnings.push(warning(format!(
+                            "can't read material library {}: {}",
+                            path.display(),
+                            source
+                        ))),
+                        Err(parse_error) => return Err(parse_error),
+                    }
+                }
+            }
+            // Smoothing groups, lines, points and free-form geometry are ignored
+            "s" | "l" | "p" | "cstype" | "deg" | "curv" | "curv2" | "surf" | "parm" | "end" => {}
+            _ => {
+                if unknown_keywords.insert(keyword.to_string()) {
+                    warnings.push(warning(format!("ignored unknown keyword '{}'", keyword)));
+                }
+            }
+        }
+    }
+    meshes.extend(builder.finish());
+
+    Ok(ObjModel { meshes, warnings })
+}
+
+/// Reads all materials of an MTL file
+///
+/// `Kd`, `Ks`, `Ke`, `Ns`, `Ni`, `d`/`Tr` and `illum` are mapped onto the closest
+/// `Material`, see `MtlDescription::to_material`. Unsupported keywords are ignored with a
+/// warning.
+pub fn load_mtl(path: &Path) -> Result<MtlLibrary, ObjError> {
+    let source = read_file(path)?;
+    parse_mtl(path, &source)
+}
+
+/// Parses the contents of an MTL file, `path` is only used in errors and warnings
+fn parse_mtl(path: &Path, source: &str) -> Result<MtlLibrary, ObjError> {
+    let mut materials = HashMap::new();
+    let mut warnings = Vec::new();
+    let mut unknown_keywords = HashSet::new();
+    let mut current: Option<(String, MtlDescription)> = None;
+
+    for (line_number, line) in logical_lines(source) {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(error("newmtl without name".to_string()));
+            }
+            if let Some((name, description)) = current.take() {
+                materials.insert(name, description.to_material());
+            }
+            current = Some((arguments.join(" "), MtlDescription::default()));
+            continue;
+        }
+
+        let description = match current.as_mut() {
+            Some((_, description)) => description,
+            None => return Err(error(format!("'{}' before newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => description.diffuse = parse_vector(&arguments, 3, 3).map_err(error)?,
+            "Ks" => description.specular = parse_vector(&arguments, 3, 3).map_err(error)?,
+            "Ke" => description.emission = parse_vector(&arguments, 3, 3).map_err(error)?,
+            "Ns" => description.shininess = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "Ni" => {
+                description.index_of_refraction = parse_floats(&arguments, 1, 1).map_err(error)?[0]
+            }
+            "d" => description.dissolve = parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "Tr" => description.dissolve = 1.0 - parse_floats(&arguments, 1, 1).map_err(error)?[0],
+            "illum" => {
+                description.illumination_model = arguments
+                    .first()
+                    .and_then(|argument| argument.parse().ok())
+                    .ok_or_else(|| error("illum needs an integer".to_string()))?
+            }
+            // Ambient color, transmission filter and texture maps are not supported
+            "Ka" | "Tf" | "sharpness" | "bump" | "disp" | "decal" | "refl" => {}
+            _ if keyword.starts_with("map_") => {}
+            _ => {
+                if unknown_keywords.insert(keyword.to_string()) {
+                    warnings.push(ObjWarning {
+                        path: path.to_path_buf(),
+                        line: line_number,
+                        message: format!("ignored unknown keyword '{}'", keyword),
+                    });
+                }
+            }
+        }
+    }
+    if let Some((name, description)) = current {
+        materials.insert(name, description.to_material());
+    }
+
+    Ok(MtlLibrary {
+        materials,
+        warnings,
+    })
+}
+
+/// Raw values of one MTL material
+struct MtlDescription {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: FloatType,
+    index_of_refraction: FloatType,
+    dissolve: FloatType,
+    illumination_model: u32,
+}
+
+impl Default for MtlDescription {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new_eq(0.8),
+            specular: Color::new_eq(0.0),
+            emission: Color::new_eq(0.0),
+            shininess: 0.0,
+            index_of_refraction: 1.5,
+            dissolve: 1.0,
+            illumination_model: 2,
+        }
+    }
+}
+
+impl MtlDescription {
+    /// Materials with an emission color become lights and transparent ones dielectrics.
+    /// Metals need a specular color and either ray traced reflections (`illum 3`) or no
+    /// diffuse color, so glossy plastics with a highlight stay lambertian. Everything else
+    /// is lambertian.
+    fn to_material(&self) -> Material {
+        let max_component = |c: &Color| c.x.max(c.y).max(c.z);
+        // Illumination models 4, 6, 7 and 9 describe glass
+        let transparent = self.dissolve < 1.0 || matches!(self.illumination_model, 4 | 6 | 7 | 9);
+
+        if max_component(&self.emission) > 0.0 {
+            Material::DiffuseLight(self.emission)
+        } else if transparent {
+            Material::Dielectric(self.index_of_refraction)
+        } else if max_component(&self.specular) > 0.0
+            && (self.illumination_model == 3 || max_component(&self.diffuse) <= 0.0)
+        {
+            // Map the Phong exponent to a roughness-like fuzz, sharp highlights mean low fuzz
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt();
+            Material::Metal(Metal::new(self.specular.into(), fuzz))
+        } else {
+            Material::Lambertian(self.diffuse.into())
+        }
+    }
+}
+
+/// Collects the triangles of the current group/material and deduplicates their vertices
+struct GroupBuilder {
+    material: Material,
+    positions: Vec<Point3>,
+    normals: Vec<Option<Vector3>>,
+    uvs: Vec<Option<(FloatType, FloatType)>>,
+    indices: Vec<[usize; 3]>,
+    /// (position, uv, normal) indices of the file to the vertex index in this group
+    lookup: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+}
+
+impl GroupBuilder {
+    fn new(material: Material) -> Self {
+        Self {
+            material,
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        key: (usize, Option<usize>, Option<usize>),
+        positions: &[Point3],
+        uvs: &[(FloatType, FloatType)],
+        normals: &[Vector3],
+    ) -> usize {
+        let next_index = self.positions.len();
+        let index = *self.lookup.entry(key).or_insert(next_index);
+        if index == next_index {
+            let (position, uv, normal) = key;
+            self.positions.push(positions[position]);
+            self.uvs.push(uv.map(|i| uvs[i]));
+            self.normals.push(normal.map(|i| normals[i]));
+        }
+        index
+    }
+
+    /// Returns the mesh, or None if the group has no faces
+    ///
+    /// Normals and uvs are only kept if every vertex of the group has one.
+    fn finish(self) -> Option<TriangleMesh> {
+        if self.indices.is_empty() {
+            return None;
+        }
+        let normals = self.normals.into_iter().collect::<Option<Vec<_>>>();
+        let uvs = self.uvs.into_iter().collect::<Option<Vec<_>>>();
+        Some(TriangleMesh::new(
+            self.positions,
+            normals.unwrap_or_default(),
+            uvs.unwrap_or_default(),
+            Vec::new(),
+            self.indices,
+            self.material,
+        ))
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Lines with comments removed and `\` continuations joined, numbered by their first line
+fn logical_lines(source: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let content = raw.split('#').next().unwrap_or("");
+        let (content, continues) = match content.trim_end().strip_suffix('\\') {
+            Some(stripped) => (stripped, true),
+            None => (content, false),
+        };
+        let (number, mut line) = pending.take().unwrap_or((index + 1, String::new()));
+        line.push(' ');
+        line.push_str(content);
+        if continues {
+            pending = Some((number, line));
+        } else {
+            lines.push((number, line));
+        }
+    }
+    lines.extend(pending);
+
+    lines
+}
+
+fn parse_floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<FloatType>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(if min == max {
+            format!("expected {} numbers, found {}", min, arguments.len())
+        } else {
+            format!(
+                "expected {} to {} numbers, found {}",
+                min,
+                max,
+                arguments.len()
+            )
+        });
+    }
+    arguments
+        .iter()
+        .map(|argument| {
+            argument
+                .parse::<FloatType>()
+                .map_err(|_| format!("'{}' is not a number", argument))
+        })
+        .collect()
+}
+
+/// Parses 3 components, extra components up to `max` (e.g. the w of positions) are ignored
+fn parse_vector(arguments: &[&str], min: usize, max: usize) -> Result<Vector3, String> {
+    let values = parse_floats(arguments, min, max)?;
+    Ok(Vector3::new(values[0], values[1], values[2]))
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn` into zero-based indices
+///
+/// `counts` are the number of positions, uvs and normals read so far, used to resolve
+/// negative (relative) indices and to reject references to undefined vertices.
+fn parse_face_vertex(
+    vertex: &str,
+    counts: (usize, usize, usize),
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let resolve = |text: &str, count: usize, kind: &str| -> Result<usize, String> {
+        let index: i64 = text
+            .parse()
+            .map_err(|_| format!("invalid {} index '{}'", kind, text))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(format!(
+                "{} index {} out of range, {} defined so far",
+                kind, index, count
+            ));
+        }
+        Ok(resolved as usize)
+    };
+
+    let mut parts = vertex.split('/');
+    let position = resolve(parts.next().unwrap_or(""), counts.0, "vertex")?;
+    let uv = match parts.next() {
+        Some(text) if !text.is_empty() => Some(resolve(text, counts.1, "texture")?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(text) if !text.is_empty() => Some(resolve(text, counts.2, "normal")?),
+        _ => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex '{}'", vertex));
+    }
+
+    Ok((position, uv, normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> ObjModel {
+        parse_obj(
+            Path::new("test.obj"),
+            source,
+            &Material::Lambertian(Color::new_eq(0.5).into()),
+        )
+        .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn mtl_material(source: &str) -> Material {
+        let library = parse_mtl(Path::new("test.mtl"), source).unwrap();
+        library.materials["test"].clone()
+    }
+
+    #[test]
+    fn face_vertex_forms() {
+        let counts = (4, 3, 2);
+        assert_eq!(parse_face_vertex("3", counts), Ok((2, None, None)));
+        assert_eq!(parse_face_vertex("3/2", counts), Ok((2, Some(1), None)));
+        assert_eq!(parse_face_vertex("3//1", counts), Ok((2, None, Some(0))));
+        assert_eq!(
+            parse_face_vertex("3/2/1", counts),
+            Ok((2, Some(1), Some(0)))
+        );
+    }
+
+    #[test]
+    fn negative_face_indices() {
+        let counts = (4, 3, 2);
+        assert_eq!(
+            parse_face_vertex("-1/-1/-1", counts),
+            Ok((3, Some(2), Some(1)))
+        );
+        assert_eq!(parse_face_vertex("-4//-2", counts), Ok((0, None, Some(0))));
+        assert_eq!(
+            parse_face_vertex("-5", counts),
+            Err("vertex index -5 out of range, 4 defined so far".to_string())
+        );
+    }
+
+    #[test]
+    fn invalid_face_vertices() {
+        let counts = (4, 3, 2);
+        assert_eq!(
+            parse_face_vertex("0", counts),
+            Err("vertex index 0 out of range, 4 defined so far".to_string())
+        );
+        assert_eq!(
+            parse_face_vertex("1/4", counts),
+            Err("texture index 4 out of range, 3 defined so far".to_string())
+        );
+        assert_eq!(
+            parse_face_vertex("1/1/1/1", counts),
+            Err("invalid face vertex '1/1/1/1'".to_string())
+        );
+        assert_eq!(
+            parse_face_vertex("a", counts),
+            Err("invalid vertex index 'a'".to_string())
+        );
+    }
+
+    #[test]
+    fn line_continuations_and_comments() {
+        let lines =
+            logical_lines("v 1 2 \\\n  3 # comment\n# only comment\nf 1 \\\n2 \\\n3\nvn 0 0 1");
+        let lines: Vec<(usize, Vec<&str>)> = lines
+            .iter()
+            .map(|(number, line)| (*number, line.split_whitespace().collect()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                (1, vec!["v", "1", "2", "3"]),
+                (3, vec![]),
+                (4, vec!["f", "1", "2", "3"]),
+                (7, vec!["vn", "0", "0", "1"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn mixed_faces() {
+        let model = parse(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\n\
+             f -4//-1 -2//-1 \\\n -1//-1\n",
+        );
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0];
+        // The two faces reference the vertices with different attributes
+        assert_eq!(mesh.indices, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(mesh.positions[5].y, 1.0);
+        // Only some vertices have uvs, so none are kept
+        assert!(mesh.uvs.is_empty());
+        assert_eq!(mesh.normals.len(), 6);
+        assert!(model.warnings.is_empty());
+    }
+
+    #[test]
+    fn obj_warnings() {
+        let model = parse(
+            "mtllib missing.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             vp 0.5\nvp 0.2\nusemtl nothing\nf 1 2 3\n",
+        );
+        let warnings: Vec<String> = model.warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(warnings.len(), 3, "{:?}", warnings);
+        assert!(warnings[0].starts_with("test.obj:1: can't read material library missing.mtl"));
+        assert_eq!(warnings[1], "test.obj:5: ignored unknown keyword 'vp'");
+        assert_eq!(
+            warnings[2],
+            "test.obj:7: unknown material 'nothing', using the default material"
+        );
+        assert!(matches!(model.meshes[0].material, Material::Lambertian(_)));
+    }
+
+    #[test]
+    fn mtl_warnings() {
+        let library = parse_mtl(
+            Path::new("test.mtl"),
+            "newmtl test\nKa 1 1 1\nmap_Kd albedo.png\nPr 0.5\nPr 0.2\nKd 0.5 0.5 0.5\n",
+        )
+        .unwrap();
+        let warnings: Vec<String> = library.warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(warnings, vec!["test.mtl:4: ignored unknown keyword 'Pr'"]);
+        assert_eq!(library.materials.len(), 1);
+    }
+
+    #[test]
+    fn mtl_to_material() {
+        assert!(matches!(
+            mtl_material("newmtl test\nKd 0.5 0.5 0.5\n"),
+            Material::Lambertian(_)
+        ));
+        assert!(matches!(
+            mtl_material("newmtl test\nKd 0.5 0.5 0.5\nKe 4 4 4\n"),
+            Material::DiffuseLight(_)
+        ));
+        assert!(matches!(
+            mtl_material("newmtl test\nd 0.5\nNi 1.33\n"),
+            Material::Dielectric(index) if index == 1.33
+        ));
+        assert!(matches!(
+            mtl_material("newmtl test\nillum 7\n"),
+            Material::Dielectric(index) if index == 1.5
+        ));
+        // Glossy plastic, the highlight doesn't make it a metal
+        assert!(matches!(
+            mtl_material("newmtl test\nKd 0.2 0.1 0.1\nKs 0.9 0.9 0.9\nNs 200\nillum 2\n"),
+            Material::Lambertian(_)
+        ));
+        assert!(matches!(
+            mtl_material("newmtl test\nKd 0.2 0.1 0.1\nKs 0.9 0.9 0.9\nillum 3\n"),
+            Material::Metal(_)
+        ));
+        assert!(matches!(
+            mtl_material("newmtl test\nKd 0 0 0\nKs 0.9 0.6 0.2\n"),
+            Material::Metal(_)
+        ));
+    }
+}
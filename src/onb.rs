@@ -0,0 +1,32 @@
+use crate::vec::Vector3;
+
+/// Orthonormal basis with `w` along a given direction
+pub struct Onb {
+    u: Vector3,
+    v: Vector3,
+    w: Vector3,
+}
+
+impl Onb {
+    pub fn from_w(direction: &Vector3) -> Self {
+        let w = direction.unit_vector();
+        let a = if w.x.abs() > 0.9 {
+            Vector3::new(0.0, 1.0, 0.0)
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    /// Transforms local coordinates into the basis
+    pub fn local(&self, a: &Vector3) -> Vector3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// Coordinates of a vector in the basis, the inverse of `local`
+    pub fn coordinates(&self, a: &Vector3) -> Vector3 {
+        Vector3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
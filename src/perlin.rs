@@ -0,0 +1,199 @@
+use crate::vec::{FloatType, Point3, Vector3};
+
+/// Number of gradients, the noise repeats after this many units
+const POINT_COUNT: usize = 256;
+
+/// Perlin gradient noise, the same seed always gives the same noise
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    permutations: [Vec<usize>; 3],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SplitMix64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| loop {
+                let gradient =
+                    Vector3::new(rng.next_signed(), rng.next_signed(), rng.next_signed());
+                // Rejecting points outside the unit sphere keeps the directions uniform
+                let length_squared = gradient.length_squared();
+                if length_squared > 1.0e-4 && length_squared <= 1.0 {
+                    break gradient.unit_vector();
+                }
+            })
+            .collect();
+        // Fisher-Yates shuffle
+        let mut permutation = || {
+            let mut permutation: Vec<usize> = (0..POINT_COUNT).collect();
+            for i in (1..POINT_COUNT).rev() {
+                permutation.swap(i, rng.next_below(i + 1));
+            }
+            permutation
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+        Self {
+            gradients,
+            permutations,
+        }
+    }
+
+    /// Smooth noise in about [-1,1], zero at integer coordinates
+    pub fn noise(&self, point: &Point3) -> FloatType {
+        let cell = [point.x.floor(), point.y.floor(), point.z.floor()];
+        let offset = [point.x - cell[0], point.y - cell[1], point.z - cell[2]];
+        // Hermite smoothing hides the grid in the interpolation
+        let weight = offset.map(|t| t * t * (3.0 - 2.0 * t));
+        let cell = cell.map(|c| c as i64);
+
+        let mut sum = 0.0;
+        for i in 0..2 {
+            for j in 0..2 {
+                for k in 0..2 {
+                    let corner = [i, j, k];
+                    let index = (0..3).fold(0, |index, axis| {
+                        let coordinate = (cell[axis] + corner[axis] as i64)
+                            .rem_euclid(POINT_COUNT as i64)
+                            as usize;
+                        index ^ self.permutations[axis][coordinate]
+                    });
+                    let towards_point = Vector3::new(
+                        offset[0] - i as FloatType,
+                        offset[1] - j as FloatType,
+                        offset[2] - k as FloatType,
+                    );
+                    let interpolation: FloatType = (0..3)
+                        .map(|axis| {
+                            if corner[axis] == 1 {
+                                weight[axis]
+                            } else {
+                                1.0 - weight[axis]
+                            }
+                        })
+                        .product();
+                    sum += interpolation * self.gradients[index].dot(&towards_point);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Sum of the absolute noise over `octaves` octaves, each with double the frequency
+    /// and half the amplitude of the one before
+    pub fn turbulence(&self, point: &Point3, octaves: usize) -> FloatType {
+        let mut sum = 0.0;
+        let mut point = *point;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * self.noise(&point).abs();
+            amplitude *= 0.5;
+            point = 2.0 * point;
+        }
+        sum
+    }
+}
+
+/// SplitMix64 generator for the tables
+///
+/// The noise must not change with the version of `rand`, whose seeded generators and
+/// shuffles don't promise to stay the same, so this fixed algorithm is used instead.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [-1,1)
+    fn next_signed(&mut self) -> FloatType {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        (2.0 * unit - 1.0) as FloatType
+    }
+
+    /// Uniform in [0,bound), the modulo bias is negligible for small bounds
+    fn next_below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Point3> {
+        (0..1000).map(|i| {
+            let i = i as FloatType;
+            Point3::new(i * 0.173 - 80.0, i * 0.311 - 150.0, i * 0.0577 + 3.0)
+        })
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        let a = Perlin::new(7);
+        let b = Perlin::new(7);
+        for point in points() {
+            assert_eq!(a.noise(&point), b.noise(&point));
+            assert_eq!(a.turbulence(&point, 7), b.turbulence(&point, 7));
+        }
+    }
+
+    #[test]
+    fn different_seed_different_noise() {
+        let a = Perlin::new(7);
+        let b = Perlin::new(8);
+        let differing = points()
+            .filter(|point| a.noise(point) != b.noise(point))
+            .count();
+        assert!(differing > 900, "only {} of 1000 values differ", differing);
+    }
+
+    #[test]
+    fn zero_at_lattice_points() {
+        let perlin = Perlin::new(3);
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    let point = Point3::new(x as FloatType, y as FloatType, z as FloatType);
+                    assert_eq!(perlin.noise(&point), 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn noise_in_range() {
+        let perlin = Perlin::new(11);
+        let (min, max) = points().fold((FloatType::MAX, FloatType::MIN), |(min, max), point| {
+            let noise = perlin.noise(&point);
+            (min.min(noise), max.max(noise))
+        });
+        assert!(min >= -1.05 && max <= 1.05, "noise in [{}, {}]", min, max);
+        // Not degenerate either
+        assert!(min < -0.2 && max > 0.2, "noise in [{}, {}]", min, max);
+    }
+    #[test]
+    fn generator_is_splitmix64() {
+        // Reference outputs of SplitMix64 seeded with 0
+        let mut rng = SplitMix64(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn noise_is_pinned() {
+        // Seeded renders are used as regression images, the noise must never change
+        let perlin = Perlin::new(42);
+        let expected = [
+            (Point3::new(-3.3, 7.1, 2.9), 0.172_507_7),
+            (Point3::new(10.6, -0.4, -5.2), -0.140_404_4),
+        ];
+        for (point, value) in expected {
+            let noise = perlin.noise(&point);
+            assert!((noise - value).abs() < 1.0e-6, "{} at {}", noise, point);
+        }
+    }
+}
@@ -0,0 +1,670 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    material::Material,
+    mesh::TriangleMesh,
+    utils::srgb_to_linear,
+    vec::{Color, FloatType, Vector3},
+};
+
+/// Error while reading a PLY file
+#[derive(Debug)]
+pub enum PlyError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Malformed header, line numbers start at 1
+    Header {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    /// Malformed or truncated body, indices start at 0
+    Data {
+        path: PathBuf,
+        element: String,
+        index: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            PlyError::Header {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            PlyError::Data {
+                path,
+                element,
+                index,
+                message,
+            } => write!(f, "{}: {} {}: {}", path.display(), element, index, message),
+        }
+    }
+}
+
+impl std::error::Error for PlyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlyError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    /// Largest value of integer types, used to normalize colors
+    fn integer_max(&self) -> Option<f64> {
+        match self {
+            ScalarType::Int8 => Some(i8::MAX as f64),
+            ScalarType::UInt8 => Some(u8::MAX as f64),
+            ScalarType::Int16 => Some(i16::MAX as f64),
+            ScalarType::UInt16 => Some(u16::MAX as f64),
+            ScalarType::Int32 => Some(i32::MAX as f64),
+            ScalarType::UInt32 => Some(u32::MAX as f64),
+            ScalarType::Float32 | ScalarType::Float64 => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum PropertyType {
+    Scalar(ScalarType),
+    List { count: ScalarType, item: ScalarType },
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    property_type: PropertyType,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property_index(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+/// Value of one property of an element
+enum Value {
+    Scalar(f64),
+    List(Vec<f64>),
+}
+
+/// Reads a Stanford PLY mesh in ascii, binary_little_endian or binary_big_endian encoding
+///
+/// Vertex positions are required, normals (`nx`/`ny`/`nz`), uvs (`u`/`v`, `s`/`t`) and
+/// colors (`red`/`green`/`blue`) are used when present. Faces with more than three vertices
+/// are triangulated as fans, other elements are skipped. Meshes with vertex colors get
+/// `Material::VertexColor`, all others `default_material`.
+pub fn load_ply(
+    path: impl AsRef<Path>,
+    default_material: &Material,
+) -> Result<TriangleMesh, PlyError> {
+    let path = path.as_ref();
+    let data = fs::read(path).map_err(|source| PlyError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_ply(path, &data, default_material)
+}
+
+/// Parses the contents of a PLY file, `path` is only used in errors
+fn parse_ply(
+    path: &Path,
+    data: &[u8],
+    default_material: &Material,
+) -> Result<TriangleMesh, PlyError> {
+    let (format, elements, body_start) = parse_header(path, data)?;
+    let mut reader = BodyReader {
+        data,
+        position: body_start,
+        format,
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+
+    for element in elements.iter() {
+        let data_error = |index: usize, message: String| PlyError::Data {
+            path: path.to_path_buf(),
+            element: element.name.clone(),
+            index,
+            message,
+        };
+
+        match element.name.as_str() {
+            "vertex" => {
+                let find_all = |names: [&[&str]; 3]| -> Option<[usize; 3]> {
+                    Some([
+                        element.property_index(names[0])?,
+                        element.property_index(names[1])?,
+                        element.property_index(names[2])?,
+                    ])
+                };
+                let position_properties = find_all([&["x"], &["y"], &["z"]]).ok_or_else(|| {
+                    data_error(0, "vertex element without x, y and z".to_string())
+                })?;
+                let normal_properties = find_all([&["nx"], &["ny"], &["nz"]]);
+                let color_properties = find_all([
+                    &["red", "r", "diffuse_red"],
+                    &["green", "g", "diffuse_green"],
+                    &["blue", "b", "diffuse_blue"],
+                ]);
+                let uv_properties = match (
+                    element.property_index(&["u", "s", "texture_u", "texture_s"]),
+                    element.property_index(&["v", "t", "texture_v", "texture_t"]),
+                ) {
+                    (Some(u), Some(v)) => Some((u, v)),
+                    _ => None,
+                };
+
+                for index in 0..element.count {
+                    let values = reader
+                        .read_element(element)
+                        .map_err(|message| data_error(index, message))?;
+                    let scalar = |property: usize| match values[property] {
+                        Value::Scalar(value) => Ok(value),
+                        Value::List(_) => Err(data_error(
+                            index,
+                            format!("property '{}' is a list", element.properties[property].name),
+                        )),
+                    };
+                    let vector = |properties: [usize; 3]| -> Result<Vector3, PlyError> {
+                        Ok(Vector3::new(
+                            scalar(properties[0])? as FloatType,
+                            scalar(properties[1])? as FloatType,
+                            scalar(properties[2])? as FloatType,
+                        ))
+                    };
+
+                    positions.push(vector(position_properties)?);
+                    if let Some(properties) = normal_properties {
+                        normals.push(vector(properties)?);
+                    }
+                    if let Some((u, v)) = uv_properties {
+                        uvs.push((scalar(u)? as FloatType, scalar(v)? as FloatType));
+                    }
+                    if let Some(properties) = color_properties {
+                        let mut color = vector(properties)?;
+                        // Integer colors are sRGB encoded, float colors are taken as linear
+                        if let PropertyType::Scalar(scalar_type) =
+                            element.properties[properties[0]].property_type
+                        {
+                            if let Some(max) = scalar_type.integer_max() {
+                                let decode = |c: FloatType| srgb_to_linear(c / max as FloatType);
+                                color =
+                                    Color::new(decode(color.x), decode(color.y), decode(color.z));
+                            }
+                        }
+                        colors.push(color);
+                    }
+                }
+            }
+            "face" => {
+                let vertex_indices = element
+                    .property_index(&["vertex_indices", "vertex_index"])
+                    .ok_or_else(|| {
+                        data_error(0, "face element without vertex_indices".to_string())
+                    })?;
+
+                for index in 0..element.count {
+                    let mut values = reader
+                        .read_element(element)
+                        .map_err(|message| data_error(index, message))?;
+                    let face = match values.swap_remove(vertex_indices) {
+                        Value::List(face) => face,
+                        Value::Scalar(_) => {
+                            return Err(data_error(
+                                index,
+                                "vertex_indices is not a list".to_string(),
+                            ))
+                        }
+                    };
+                    if face.len() < 3 {
+                        return Err(data_error(
+                            index,
+                            format!("face needs at least 3 vertices, found {}", face.len()),
+                        ));
+                    }
+                    let face = face
+                        .into_iter()
+                        .map(|vertex| {
+                            if vertex >= 0.0 && (vertex as usize) < positions.len() {
+                                Ok(vertex as usize)
+                            } else {
+                                Err(data_error(
+                                    index,
+                                    format!(
+                                        "vertex index {} out of range, {} vertices",
+                                        vertex,
+                                        positions.len()
+                                    ),
+                                ))
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    for i in 1..face.len() - 1 {
+                        indices.push([face[0], face[i], face[i + 1]]);
+                    }
+                }
+            }
+            _ => {
+                for index in 0..element.count {
+                    reader
+                        .read_element(element)
+                        .map_err(|message| data_error(index, message))?;
+                }
+            }
+        }
+    }
+
+    let material = if colors.is_empty() {
+        default_material.clone()
+    } else {
+        Material::VertexColor
+    };
+    Ok(TriangleMesh::new(
+        positions, normals, uvs, colors, indices, material,
+    ))
+}
+
+/// Parses the header and returns the body format, the element layout and the body offset
+fn parse_header(path: &Path, data: &[u8]) -> Result<(Format, Vec<Element>, usize), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut position = 0;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+        let header_error = |message: String| PlyError::Header {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let end = data[position..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|offset| position + offset)
+            .ok_or_else(|| header_error("missing end_header".to_string()))?;
+        let line = std::str::from_utf8(&data[position..end])
+            .map_err(|_| header_error("header is not valid text".to_string()))?
+            .trim_end_matches('\r');
+        position = end + 1;
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if line_number == 1 {
+            if tokens != ["ply"] {
+                return Err(header_error("not a PLY file".to_string()));
+            }
+            continue;
+        }
+
+        match tokens.as_slice() {
+            [] | ["comment", ..] | ["obj_info", ..] => {}
+            ["format", name, version] => {
+                if *version != "1.0" {
+                    return Err(header_error(format!("unsupported version {}", version)));
+                }
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(header_error(format!("unknown format '{}'", name))),
+                });
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| header_error(format!("invalid element count '{}'", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, item, name] => {
+                let parse = |name: &str| {
+                    ScalarType::parse(name)
+                        .ok_or_else(|| header_error(format!("unknown type '{}'", name)))
+                };
+                let property = Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::List {
+                        count: parse(count)?,
+                        item: parse(item)?,
+                    },
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| header_error("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["property", scalar, name] => {
+                let property = Property {
+                    name: name.to_string(),
+                    property_type: PropertyType::Scalar(
+                        ScalarType::parse(scalar)
+                            .ok_or_else(|| header_error(format!("unknown type '{}'", scalar)))?,
+                    ),
+                };
+                elements
+                    .last_mut()
+                    .ok_or_else(|| header_error("property before element".to_string()))?
+                    .properties
+                    .push(property);
+            }
+            ["end_header"] => {
+                let format = format.ok_or_else(|| header_error("missing format".to_string()))?;
+                return Ok((format, elements, position));
+            }
+            _ => return Err(header_error(format!("unexpected header line '{}'", line))),
+        }
+    }
+}
+
+/// Reads element values from the body in any of the three encodings
+struct BodyReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    format: Format,
+}
+
+impl<'a> BodyReader<'a> {
+    fn read_element(&mut self, element: &Element) -> Result<Vec<Value>, String> {
+        element
+            .properties
+            .iter()
+            .map(|property| match property.property_type {
+                PropertyType::Scalar(scalar_type) => {
+                    Ok(Value::Scalar(self.read_scalar(scalar_type)?))
+                }
+                PropertyType::List { count, item } => {
+                    let count = self.read_scalar(count)?;
+                    if count < 0.0 {
+                        return Err(format!("negative list length in '{}'", property.name));
+                    }
+                    (0..count as usize)
+                        .map(|_| self.read_scalar(item))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map(Value::List)
+                }
+            })
+            .collect()
+    }
+
+    fn read_scalar(&mut self, scalar_type: ScalarType) -> Result<f64, String> {
+        match self.format {
+            Format::Ascii => self.read_ascii(),
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => self.read_binary(scalar_type),
+        }
+    }
+
+    fn read_ascii(&mut self) -> Result<f64, String> {
+        let data = &self.data[self.position..];
+        let start = data
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        let length = data[start..]
+            .iter()
+            .position(|byte| byte.is_ascii_whitespace())
+            .unwrap_or(data.len() - start);
+        self.position += start + length;
+
+        let token = String::from_utf8_lossy(&data[start..start + length]);
+        token
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", token))
+    }
+
+    fn read_binary(&mut self, scalar_type: ScalarType) -> Result<f64, String> {
+        let size = scalar_type.size();
+        let bytes = self
+            .data
+            .get(self.position..self.position + size)
+            .ok_or_else(|| "unexpected end of file".to_string())?;
+        self.position += size;
+
+        let mut buffer = [0u8; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            buffer[..size].reverse();
+        }
+        Ok(match scalar_type {
+            ScalarType::Int8 => buffer[0] as i8 as f64,
+            ScalarType::UInt8 => buffer[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+            ScalarType::Int32 => {
+                i32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::UInt32 => {
+                u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::Float32 => {
+                f32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as f64
+            }
+            ScalarType::Float64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.5],
+        [0.0, 1.0, -0.25],
+    ];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 128, 0], [0, 0, 64], [255, 255, 255]];
+    /// A quad, which becomes two triangles, and a triangle
+    const FACES: [&[i32]; 2] = [&[0, 1, 2, 3], &[3, 2, 1]];
+
+    /// The test mesh in the given encoding with uchar colors
+    fn encode(format: &str) -> Vec<u8> {
+        let mut data = format!(
+            "ply\nformat {} 1.0\ncomment test mesh\n\
+             element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face 2\nproperty list uchar int vertex_indices\nend_header\n",
+            format
+        )
+        .into_bytes();
+        for (position, color) in POSITIONS.iter().zip(COLORS.iter()) {
+            match format {
+                "ascii" => data.extend(
+                    format!(
+                        "{} {} {} {} {} {}\n",
+                        position[0], position[1], position[2], color[0], color[1], color[2]
+                    )
+                    .bytes(),
+                ),
+                _ => {
+                    for value in position {
+                        if format == "binary_little_endian" {
+                            data.extend(value.to_le_bytes());
+                        } else {
+                            data.extend(value.to_be_bytes());
+                        }
+                    }
+                    data.extend(color);
+                }
+            }
+        }
+        for face in FACES {
+            match format {
+                "ascii" => {
+                    let indices: Vec<String> = face.iter().map(|i| i.to_string()).collect();
+                    data.extend(format!("{} {}\n", face.len(), indices.join(" ")).bytes());
+                }
+                _ => {
+                    data.push(face.len() as u8);
+                    for index in face {
+                        if format == "binary_little_endian" {
+                            data.extend(index.to_le_bytes());
+                        } else {
+                            data.extend(index.to_be_bytes());
+                        }
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn components(vectors: &[Vector3]) -> Vec<[FloatType; 3]> {
+        vectors.iter().map(|v| [v.x, v.y, v.z]).collect()
+    }
+
+    fn parse(data: &[u8]) -> Result<TriangleMesh, PlyError> {
+        parse_ply(Path::new("test.ply"), data, &Material::Dielectric(1.5))
+    }
+
+    fn error_message(data: &[u8]) -> String {
+        parse(data)
+            .err()
+            .expect("Parsing should have failed")
+            .to_string()
+    }
+
+    #[test]
+    fn same_mesh_in_all_encodings() {
+        for format in ["ascii", "binary_little_endian", "binary_big_endian"] {
+            let mesh = parse(&encode(format)).unwrap();
+            assert_eq!(components(&mesh.positions), POSITIONS, "{}", format);
+            assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3], [3, 2, 1]]);
+            assert!(matches!(mesh.material, Material::VertexColor));
+
+            // uchar colors are sRGB encoded
+            let decode = |c: u8| srgb_to_linear(c as FloatType / 255.0);
+            let colors: Vec<[FloatType; 3]> = COLORS
+                .iter()
+                .map(|c| [decode(c[0]), decode(c[1]), decode(c[2])])
+                .collect();
+            assert_eq!(components(&mesh.colors), colors, "{}", format);
+            assert_eq!(components(&mesh.colors[3..]), [[1.0; 3]]);
+            assert!((mesh.colors[1].g() - 0.2158).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn float_colors_are_linear() {
+        let data = b"ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float red\nproperty float green\nproperty float blue\n\
+            element face 1\nproperty list uchar uint vertex_indices\nend_header\n\
+            0 0 0 0.5 0.25 1\n1 0 0 0 0 0\n0 1 0 1 1 1\n3 0 1 2\n";
+        let mesh = parse(data).unwrap();
+        assert_eq!(components(&mesh.colors[..1]), [[0.5, 0.25, 1.0]]);
+    }
+
+    #[test]
+    fn without_colors_uses_default_material() {
+        let data = b"ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+        let mesh = parse(data).unwrap();
+        assert!(mesh.colors.is_empty());
+        assert!(matches!(mesh.material, Material::Dielectric(_)));
+    }
+
+    #[test]
+    fn face_index_out_of_range() {
+        let data = b"ply\nformat ascii 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 2\nproperty list uchar int vertex_indices\nend_header\n\
+            0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n3 0 1 3\n";
+        assert_eq!(
+            error_message(data),
+            "test.ply: face 1: vertex index 3 out of range, 3 vertices"
+        );
+    }
+
+    #[test]
+    fn truncated_body() {
+        for format in ["ascii", "binary_little_endian", "binary_big_endian"] {
+            let data = encode(format);
+            // Cut into the third vertex
+            let header_end = data.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+            let vertex_size = if format == "ascii" {
+                data[header_end..].iter().position(|&b| b == b'\n').unwrap() + 1
+            } else {
+                15
+            };
+            let truncated = &data[..header_end + 2 * vertex_size + 3];
+            assert_eq!(
+                error_message(truncated),
+                "test.ply: vertex 2: unexpected end of file",
+                "{}",
+                format
+            );
+        }
+    }
+}
@@ -0,0 +1,503 @@
+//! Principled BSDF after Burley, "Physically-Based Shading at Disney" (2012) and "Extending
+//! the Disney BRDF to a BSDF with Integrated Subsurface Scattering" (2015)
+//!
+//! Directions are in the local frame of `microfacet` with the shading normal along +z.
+use std::sync::Arc;
+
+use crate::{
+    hittable::HitRecord,
+    microfacet::{fresnel_dielectric, reflect, MicrofacetSample, RoughDielectric, TrowbridgeReitz},
+    texture::Texture,
+    utils::{luminance, random_float},
+    vec::{Color, FloatType, Vector3, PI},
+};
+
+/// Lower bound of the roughness, keeps every lobe glossy so it can be combined with light
+/// samples
+const MIN_ROUGHNESS: FloatType = 0.05;
+/// Roughness of the clear coat
+const CLEARCOAT_ROUGHNESS: FloatType = 0.1;
+/// Refractive index of the clear coat
+const CLEARCOAT_INDEX_OF_REFRACTION: FloatType = 1.5;
+
+/// One material for plastics, metals, glass and everything in between. Textures of the
+/// scalar parameters are read as the mean of their color channels, all of them except the
+/// refractive index are clamped to [0,1].
+#[derive(Clone)]
+pub struct Principled {
+    /// Diffuse albedo of dielectrics, reflectance of metals and tint of transmission
+    pub base_color: Arc<dyn Texture>,
+    /// Blends from dielectric to metal
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    /// Reflectance of dielectrics at normal incidence, 0.5 is 4 %
+    pub specular: Arc<dyn Texture>,
+    /// Tints the reflection of dielectrics towards the base color
+    pub specular_tint: Arc<dyn Texture>,
+    /// Extra reflection at grazing angles, e.g. for cloth
+    pub sheen: Arc<dyn Texture>,
+    /// Strength of a glossy, colorless coat on top
+    pub clearcoat: Arc<dyn Texture>,
+    /// Blends from opaque to glass
+    pub transmission: Arc<dyn Texture>,
+    /// Refractive index of the transmission
+    pub index_of_refraction: Arc<dyn Texture>,
+}
+
+impl Default for Principled {
+    fn default() -> Self {
+        let constant = |value| Color::new_eq(value).into();
+        Self {
+            base_color: constant(0.8),
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            clearcoat: constant(0.0),
+            transmission: constant(0.0),
+            index_of_refraction: constant(1.5),
+        }
+    }
+}
+
+impl Principled {
+    /// The BSDF with the parameters at the hit
+    pub fn bsdf(&self, hit_record: &HitRecord) -> PrincipledBsdf {
+        let value = |texture: &Arc<dyn Texture>| {
+            texture.value(hit_record.u, hit_record.v, &hit_record.point)
+        };
+        let scalar = |texture: &Arc<dyn Texture>| {
+            let color = value(texture);
+            (color.x + color.y + color.z) / 3.0
+        };
+        let unit = |texture: &Arc<dyn Texture>| scalar(texture).clamp(0.0, 1.0);
+
+        let base_color = value(&self.base_color).max(&Color::new_eq(0.0));
+        let metallic = unit(&self.metallic);
+        let roughness = unit(&self.roughness).max(MIN_ROUGHNESS);
+        let transmission = unit(&self.transmission);
+        let index_of_refraction = scalar(&self.index_of_refraction).max(1.0e-3);
+
+        let base_luminance = luminance(&base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color / base_luminance
+        } else {
+            Color::new_eq(1.0)
+        };
+        let specular_tint = unit(&self.specular_tint);
+        let specular_color = (1.0 - specular_tint) * Color::new_eq(1.0) + specular_tint * tint;
+
+        PrincipledBsdf {
+            base_color,
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            metallic,
+            glass_weight: (1.0 - metallic) * transmission,
+            dielectric_reflectance: 0.08 * unit(&self.specular) * specular_color,
+            sheen: unit(&self.sheen),
+            clearcoat: unit(&self.clearcoat),
+            roughness,
+            distribution: TrowbridgeReitz::new(roughness),
+            clearcoat_distribution: TrowbridgeReitz::new(CLEARCOAT_ROUGHNESS),
+            glass: RoughDielectric::new(index_of_refraction, roughness),
+            facing_front: hit_record.facing_front,
+        }
+    }
+}
+
+/// Principled BSDF at one point of the surface
+pub struct PrincipledBsdf {
+    base_color: Color,
+    /// Share of the diffuse and sheen lobes
+    diffuse_weight: FloatType,
+    metallic: FloatType,
+    /// Share of the rough glass lobe
+    glass_weight: FloatType,
+    /// Reflectance of the dielectric part at normal incidence
+    dielectric_reflectance: Color,
+    sheen: FloatType,
+    clearcoat: FloatType,
+    roughness: FloatType,
+    distribution: TrowbridgeReitz,
+    clearcoat_distribution: TrowbridgeReitz,
+    glass: RoughDielectric,
+    /// Outside of the surface, decides which way the glass lobe refracts. The other lobes
+    /// reflect the same on both sides of two-sided surfaces.
+    facing_front: bool,
+}
+
+/// Schlick's approximation of the Fresnel reflectance with `f0` at normal incidence
+fn schlick(f0: &Color, cos_theta: FloatType) -> Color {
+    let weight = (1.0 - cos_theta).clamp(0.0, 1.0).powi(5);
+    *f0 + weight * (Color::new_eq(1.0) - *f0)
+}
+
+impl PrincipledBsdf {
+    /// Fresnel reflectance of the specular lobe, the dielectric and metallic parts combined
+    fn specular_fresnel(&self, cos_theta: FloatType) -> Color {
+        self.diffuse_weight * schlick(&self.dielectric_reflectance, cos_theta)
+            + self.metallic * schlick(&self.base_color, cos_theta)
+    }
+
+    /// Share of the light that passes the clear coat along `w`
+    fn clearcoat_transmittance(&self, w: &Vector3) -> FloatType {
+        1.0 - self.clearcoat * fresnel_dielectric(w.z.abs(), CLEARCOAT_INDEX_OF_REFRACTION)
+    }
+
+    /// Probabilities of sampling the diffuse, specular, clear coat and glass lobes, not
+    /// normalized
+    fn lobe_weights(&self, wo: &Vector3) -> [FloatType; 4] {
+        [
+            self.diffuse_weight,
+            luminance(&self.specular_fresnel(wo.z)),
+            self.clearcoat * fresnel_dielectric(wo.z, CLEARCOAT_INDEX_OF_REFRACTION),
+            self.glass_weight,
+        ]
+    }
+
+    /// Rough glass with the refracted light tinted by the base color on the way in and out
+    fn eval_glass(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        let value = self.glass.eval(wo, wi, self.facing_front);
+        if wo.z * wi.z < 0.0 {
+            let tint = Color::new(
+                self.base_color.x.sqrt(),
+                self.base_color.y.sqrt(),
+                self.base_color.z.sqrt(),
+            );
+            tint * value
+        } else {
+            value
+        }
+    }
+
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        // The coat reflects what it doesn't let through to the layers below
+        let transmittance = self.clearcoat_transmittance(wo);
+        let glass = self.glass_weight * self.eval_glass(wo, wi);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return transmittance * glass;
+        }
+        let wm = (*wo + *wi).unit_vector();
+        let cos_d = wi.dot(&wm);
+
+        // Burley's diffuse with retro-reflection at grazing angles, renormalized after
+        // Lagarde and de Rousiers, "Moving Frostbite to Physically Based Rendering" (2014)
+        let alpha = self.roughness * self.roughness;
+        let energy_bias = 0.5 * alpha;
+        let energy_factor = 1.0 + (1.0 / 1.51 - 1.0) * alpha;
+        let fresnel_90 = energy_bias + 2.0 * alpha * cos_d * cos_d;
+        let diffuse_fresnel =
+            |cos: FloatType| 1.0 + (fresnel_90 - 1.0) * (1.0 - cos).clamp(0.0, 1.0).powi(5);
+        let diffuse = energy_factor * diffuse_fresnel(wo.z) * diffuse_fresnel(wi.z) / PI
+            * self.base_color
+            + Color::new_eq(self.sheen * (1.0 - cos_d).clamp(0.0, 1.0).powi(5));
+        // The diffuse layer gets what the dielectric specular reflection lets through
+        let dielectric_transmittance =
+            |cos: FloatType| Color::new_eq(1.0) - schlick(&self.dielectric_reflectance, cos);
+        let diffuse = dielectric_transmittance(wo.z) * dielectric_transmittance(wi.z) * diffuse;
+
+        let specular = self.distribution.d(&wm) * self.distribution.g(wo, wi) / (4.0 * wo.z)
+            * self.specular_fresnel(cos_d);
+
+        let clearcoat = self.clearcoat
+            * self.clearcoat_distribution.d(&wm)
+            * self.clearcoat_distribution.g(wo, wi)
+            / (4.0 * wo.z)
+            * fresnel_dielectric(cos_d, CLEARCOAT_INDEX_OF_REFRACTION);
+
+        transmittance
+            * self.clearcoat_transmittance(wi)
+            * (self.diffuse_weight * wi.z * diffuse + specular + glass)
+            + Color::new_eq(clearcoat)
+    }
+
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3) -> FloatType {
+        let weights = self.lobe_weights(wo);
+        let total: FloatType = weights.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let mut pdf = weights[3] * self.glass.pdf(wo, wi, self.facing_front);
+        if wo.z > 0.0 && wi.z > 0.0 {
+            let wm = (*wo + *wi).unit_vector();
+            let reflection = |distribution: &TrowbridgeReitz| {
+                distribution.visible_pdf(wo, &wm) / (4.0 * wo.dot(&wm).abs())
+            };
+            pdf += weights[0] * wi.z / PI
+                + weights[1] * reflection(&self.distribution)
+                + weights[2] * reflection(&self.clearcoat_distribution);
+        }
+        pdf / total
+    }
+
+    pub fn sample(&self, wo: &Vector3) -> Option<MicrofacetSample> {
+        let weights = self.lobe_weights(wo);
+        let total: FloatType = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut choice = random_float() * total;
+        let lobe = weights
+            .iter()
+            .position(|weight| {
+                choice -= weight;
+                choice < 0.0
+            })
+            .unwrap_or(3);
+        let wi = match lobe {
+            0 => Vector3::random_cosine_direction(),
+            1 => reflect(wo, &self.distribution.sample_visible_normal(wo)),
+            2 => reflect(wo, &self.clearcoat_distribution.sample_visible_normal(wo)),
+            _ => self.glass.sample(wo, self.facing_front)?.wi,
+        };
+        let pdf = self.pdf(wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(MicrofacetSample {
+            wi,
+            value: self.eval(wo, &wi),
+            pdf,
+            is_specular: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::Material, microfacet::refract, onb::Onb, ray::Ray, utils::reseed_rng, vec::Point3,
+    };
+
+    fn constant(value: FloatType) -> Arc<dyn Texture> {
+        Color::new_eq(value).into()
+    }
+
+    /// White materials with the strongest dielectric reflection, one per lobe and a blend
+    fn mixes(roughness: FloatType) -> Vec<(&'static str, Principled)> {
+        let white = Principled {
+            base_color: constant(1.0),
+            roughness: constant(roughness),
+            specular: constant(1.0),
+            ..Default::default()
+        };
+        vec![
+            ("diffuse", white.clone()),
+            (
+                "sheen",
+                Principled {
+                    sheen: constant(1.0),
+                    ..white.clone()
+                },
+            ),
+            (
+                "metallic",
+                Principled {
+                    metallic: constant(1.0),
+                    ..white.clone()
+                },
+            ),
+            (
+                "clearcoat",
+                Principled {
+                    clearcoat: constant(1.0),
+                    ..white.clone()
+                },
+            ),
+            (
+                "transmission",
+                Principled {
+                    transmission: constant(1.0),
+                    ..white.clone()
+                },
+            ),
+            (
+                "blend",
+                Principled {
+                    metallic: constant(0.3),
+                    sheen: constant(1.0),
+                    clearcoat: constant(1.0),
+                    transmission: constant(0.3),
+                    ..white
+                },
+            ),
+        ]
+    }
+
+    /// The BSDF on the outside of a surface facing +z
+    fn bsdf(principled: &Principled) -> PrincipledBsdf {
+        let material = Material::Principled(principled.clone());
+        let ray = Ray::new(Point3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let hit_record = HitRecord::new_face_normal(
+            Point3::new_eq(0.0),
+            1.0,
+            &ray,
+            Vector3::new(0.0, 0.0, 1.0),
+            (0.5, 0.5),
+            &material,
+        );
+        principled.bsdf(&hit_record)
+    }
+
+    /// Directions towards the viewer from straight above to grazing
+    fn views() -> Vec<Vector3> {
+        [0.0 as FloatType, 30.0, 60.0, 85.0, 89.0]
+            .iter()
+            .map(|angle| {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                Vector3::new(sin, 0.0, cos)
+            })
+            .collect()
+    }
+
+    /// Mean of the sample weights, the share of the light from `wo` that is scattered
+    fn sampled_albedo(bsdf: &PrincipledBsdf, wo: &Vector3, count: usize) -> Color {
+        let mut sum = Color::new_eq(0.0);
+        for sample in (0..count).filter_map(|_| bsdf.sample(wo)) {
+            sum += sample.value / sample.pdf;
+        }
+        sum / count as FloatType
+    }
+
+    #[test]
+    fn conserves_energy() {
+        reseed_rng(1);
+        for roughness in [0.05, 0.3, 0.6, 1.0] {
+            for (name, principled) in mixes(roughness) {
+                let bsdf = bsdf(&principled);
+                for wo in views() {
+                    let albedo = sampled_albedo(&bsdf, &wo, 5_000);
+                    assert!(
+                        (0..3).all(|axis| albedo[axis] <= 1.01),
+                        "{} at roughness {} reflects {} from {}",
+                        name,
+                        roughness,
+                        albedo,
+                        wo
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        reseed_rng(2);
+        for roughness in [0.05, 0.5, 1.0] {
+            for (name, principled) in mixes(roughness) {
+                let bsdf = bsdf(&principled);
+                for wo in views() {
+                    for _ in 0..1000 {
+                        let sample = match bsdf.sample(&wo) {
+                            Some(sample) => sample,
+                            None => continue,
+                        };
+                        let pdf = bsdf.pdf(&wo, &sample.wi);
+                        let value = bsdf.eval(&wo, &sample.wi);
+                        assert!((sample.pdf - pdf).abs() <= 1e-3 * pdf, "{}", name);
+                        assert!((sample.value - value).length() <= 1e-3 * value.length());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Integral of `pdf` over the directions with a cosine above `cos_max` to `axis`, with
+    /// the midpoint rule on polar angles that grow quadratically, which resolves lobes much
+    /// narrower than the cone around its axis
+    fn cone_integral(
+        pdf: impl Fn(&Vector3) -> FloatType,
+        axis: &Vector3,
+        cos_max: FloatType,
+    ) -> FloatType {
+        let (polar_steps, azimuth_steps) = (300, 48);
+        let frame = Onb::from_w(axis);
+        let theta_max = cos_max.acos();
+        let dt = 1.0 / polar_steps as FloatType;
+        let d_phi = 2.0 * PI / azimuth_steps as FloatType;
+        let mut sum = 0.0;
+        for i in 0..polar_steps {
+            // theta = theta_max t², so d theta = 2 theta_max t dt
+            let t = (i as FloatType + 0.5) * dt;
+            let (sin_theta, cos_theta) = (theta_max * t * t).sin_cos();
+            let d_theta = 2.0 * theta_max * t * dt;
+            for j in 0..azimuth_steps {
+                let (sin_phi, cos_phi) = ((j as FloatType + 0.5) * d_phi).sin_cos();
+                let local = Vector3::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta);
+                sum += pdf(&frame.local(&local)) * sin_theta * d_theta;
+            }
+        }
+        sum * d_phi
+    }
+
+    #[test]
+    fn pdf_is_sampling_density() {
+        // The share of the samples inside a cone is the pdf integrated over it. Small cones
+        // around the mirror and refracted directions tell apart the narrow lobes.
+        reseed_rng(3);
+        let count = 20_000;
+        for roughness in [0.05, 1.0] {
+            for (name, principled) in mixes(roughness) {
+                let bsdf = bsdf(&principled);
+                for wo in views().iter().take(4) {
+                    let normal = Vector3::new(0.0, 0.0, 1.0);
+                    let cones = [
+                        (reflect(wo, &normal), 0.999),
+                        (reflect(wo, &normal), 0.99),
+                        (normal, 0.9),
+                        (refract(wo, &normal, 1.5).unwrap(), 0.99),
+                    ];
+                    let samples: Vec<Vector3> = (0..count)
+                        .filter_map(|_| bsdf.sample(wo))
+                        .map(|sample| sample.wi)
+                        .collect();
+                    for (axis, cos_max) in cones.iter() {
+                        let inside = samples.iter().filter(|wi| wi.dot(axis) > *cos_max).count()
+                            as FloatType
+                            / count as FloatType;
+                        let integral = cone_integral(|wi| bsdf.pdf(wo, wi), axis, *cos_max);
+                        assert!(
+                            (inside - integral).abs() < 0.01 + 0.05 * integral,
+                            "{} at roughness {} has {} of the samples in a cone with {} from {}",
+                            name,
+                            roughness,
+                            inside,
+                            integral,
+                            wo
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_albedo_matches_eval() {
+        // The metallic and transmission lobes don't cover the hemisphere, their importance
+        // sampled albedo is compared to integrating eval with uniform directions instead
+        reseed_rng(4);
+        let count = 100_000;
+        for (name, principled) in mixes(0.6) {
+            if name != "metallic" && name != "transmission" {
+                continue;
+            }
+            let bsdf = bsdf(&principled);
+            for wo in views().iter().take(3) {
+                let sampled = sampled_albedo(&bsdf, wo, 20_000);
+                let mut sum = Color::new_eq(0.0);
+                for _ in 0..count {
+                    sum += bsdf.eval(wo, &Vector3::random_unit_vector());
+                }
+                let uniform = 4.0 * PI * sum / count as FloatType;
+                assert!(
+                    (sampled - uniform).length() < 0.05,
+                    "{} sampled {} vs {}",
+                    name,
+                    sampled,
+                    uniform
+                );
+            }
+        }
+    }
+}
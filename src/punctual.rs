@@ -0,0 +1,109 @@
+use crate::vec::{Color, FloatType, Point3, Vector3};
+
+/// Light source without area that can only be reached by shadow rays
+pub enum PunctualLight {
+    /// Shines equally in all directions, `intensity` is the radiant intensity (W/sr)
+    Point { position: Point3, intensity: Color },
+    /// Point light restricted to a cone around `direction`, fading out between the inner and
+    /// the outer angle
+    Spot {
+        position: Point3,
+        direction: Vector3,
+        intensity: Color,
+        cos_inner: FloatType,
+        cos_outer: FloatType,
+    },
+    /// Infinitely far away light like the sun, shining along `direction` with the given
+    /// irradiance (W/m²)
+    Directional {
+        direction: Vector3,
+        irradiance: Color,
+    },
+}
+
+impl PunctualLight {
+    pub fn point(position: Point3, intensity: Color) -> Self {
+        PunctualLight::Point {
+            position,
+            intensity,
+        }
+    }
+
+    /// The angles are measured from the cone axis in degrees
+    pub fn spot(
+        position: Point3,
+        direction: Vector3,
+        intensity: Color,
+        inner_angle: FloatType,
+        outer_angle: FloatType,
+    ) -> Self {
+        let outer_angle = outer_angle.clamp(0.0, 180.0);
+        PunctualLight::Spot {
+            position,
+            direction: direction.unit_vector(),
+            intensity,
+            cos_inner: inner_angle.clamp(0.0, outer_angle).to_radians().cos(),
+            cos_outer: outer_angle.to_radians().cos(),
+        }
+    }
+
+    pub fn directional(direction: Vector3, irradiance: Color) -> Self {
+        PunctualLight::Directional {
+            direction: direction.unit_vector(),
+            irradiance,
+        }
+    }
+
+    /// Light arriving at `point`: the unit direction towards the light, the distance to it
+    /// and the incident radiance integrated over the light (irradiance on a surface facing it)
+    pub fn illuminate(&self, point: &Point3) -> Option<(Vector3, FloatType, Color)> {
+        match self {
+            PunctualLight::Point {
+                position,
+                intensity,
+            } => {
+                let (direction, distance) = towards(point, position)?;
+                Some((direction, distance, *intensity / (distance * distance)))
+            }
+            PunctualLight::Spot {
+                position,
+                direction: axis,
+                intensity,
+                cos_inner,
+                cos_outer,
+            } => {
+                let (direction, distance) = towards(point, position)?;
+                let cosine = -direction.dot(axis);
+                if cosine <= *cos_outer {
+                    return None;
+                }
+                // Smooth falloff from the inner to the outer cone
+                let falloff = if cosine >= *cos_inner {
+                    1.0
+                } else {
+                    let t = (cosine - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                Some((
+                    direction,
+                    distance,
+                    falloff / (distance * distance) * *intensity,
+                ))
+            }
+            PunctualLight::Directional {
+                direction,
+                irradiance,
+            } => Some((-*direction, FloatType::INFINITY, *irradiance)),
+        }
+    }
+}
+
+fn towards(point: &Point3, position: &Point3) -> Option<(Vector3, FloatType)> {
+    let offset = *position - *point;
+    let distance = offset.length();
+    if distance > 0.0 {
+        Some((offset / distance, distance))
+    } else {
+        None
+    }
+}
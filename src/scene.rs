@@ -0,0 +1,84 @@
+use crate::{
+    camera::Camera,
+    environment::Environment,
+    hittable::HittableList,
+    punctual::PunctualLight,
+    vec::{FloatType, Point3, Vector3},
+};
+
+/// Resolution and quality settings of a render
+#[derive(Debug, Clone)]
+pub struct ImageSettings {
+    pub width: usize,
+    pub aspect_ratio: FloatType,
+    pub samples_per_pixel: usize,
+    pub max_depth: usize,
+}
+
+impl ImageSettings {
+    pub fn height(&self) -> usize {
+        ((self.width as FloatType / self.aspect_ratio) as usize).max(1)
+    }
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            width: 1200,
+            aspect_ratio: 3.0 / 2.0,
+            samples_per_pixel: 10,
+            max_depth: 50,
+        }
+    }
+}
+
+/// Everything needed to build a `Camera` once the aspect ratio is known
+#[derive(Debug, Clone)]
+pub struct CameraSettings {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub view_up: Vector3,
+    pub vertical_fov: FloatType,
+    pub aperture: FloatType,
+    pub focus_distance: FloatType,
+}
+
+impl CameraSettings {
+    pub fn build(&self, aspect_ratio: FloatType) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            self.vertical_fov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_distance,
+        )
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            view_up: Vector3::new(0.0, 1.0, 0.0),
+            vertical_fov: 20.0,
+            aperture: 0.1,
+            focus_distance: 10.0,
+        }
+    }
+}
+
+/// Objects and their surrounding together with the camera and image settings to render them
+/// with
+pub struct Scene {
+    pub image: ImageSettings,
+    pub camera: CameraSettings,
+    pub world: HittableList,
+    pub environment: Environment,
+    /// Lights without geometry, emissive objects are part of the world
+    pub lights: Vec<PunctualLight>,
+    /// Problems that didn't stop loading, like a missing material library of a model
+    pub warnings: Vec<String>,
+}
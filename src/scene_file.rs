@@ -0,0 +1,1309 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use toml::{value::Table, Value};
+
+use crate::{
+    bvh::{BvhNode, SplitMethod},
+    constant_medium::ConstantMedium,
+    environment::{Environment, EnvironmentMap},
+    hittable::HittableList,
+    image::{read_image, read_image_data},
+    material::{Material, Metal},
+    mesh::TriangleMesh,
+    microfacet::{metal_names, named_metal, Coat, Conductor, RoughDielectric},
+    normal_map::NormalMap,
+    obj::{load_obj, ObjError},
+    ply::{load_ply, PlyError},
+    principled::Principled,
+    punctual::PunctualLight,
+    scene::{CameraSettings, ImageSettings, Scene},
+    sky::{Sky, SUN_DIAMETER},
+    sphere::Sphere,
+    texture::{Checker, CheckerSpace, ImageTexture, NoisePattern, NoiseTexture, Texture, WrapMode},
+    triangle::Triangle,
+    vec::{Color, FloatType, Point3, Vector3},
+};
+
+/// Error while loading a scene file
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// The file is not valid TOML
+    Syntax {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    /// A value is missing or invalid, `key` is its dotted path (e.g. `objects[2].radius`)
+    Invalid {
+        path: PathBuf,
+        key: String,
+        message: String,
+    },
+    Obj {
+        path: PathBuf,
+        key: String,
+        source: Box<ObjError>,
+    },
+    Ply {
+        path: PathBuf,
+        key: String,
+        source: Box<PlyError>,
+    },
+    Image {
+        path: PathBuf,
+        key: String,
+        source: io::Error,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Syntax { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Invalid { path, key, message } => {
+                write!(f, "{}: {}: {}", path.display(), key, message)
+            }
+            SceneError::Obj { path, key, source } => {
+                write!(f, "{}: {}: {}", path.display(), key, source)
+            }
+            SceneError::Ply { path, key, source } => {
+                write!(f, "{}: {}: {}", path.display(), key, source)
+            }
+            SceneError::Image { path, key, source } => {
+                write!(f, "{}: {}: {}", path.display(), key, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Syntax { source, .. } => Some(source),
+            SceneError::Invalid { .. } => None,
+            SceneError::Obj { source, .. } => Some(source.as_ref()),
+            SceneError::Ply { source, .. } => Some(source.as_ref()),
+            SceneError::Image { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Loads a TOML scene description
+///
+/// The file has an `[image]` and a `[camera]` table and an optional `[environment]` table.
+/// Textures are named in `[textures.<name>]`, materials in `[materials.<name>]` refer to
+/// them by name and the `[[objects]]` array refers to the materials by name. Point, spot
+/// and directional lights go into the `[[lights]]` array. Paths of models, images and
+/// textures are relative to the scene file. See `scenes/` for examples.
+pub fn load_scene(path: impl AsRef<Path>) -> Result<Scene> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene(path, &source)
+}
+
+/// Parses the contents of a scene file, relative paths in it start at the directory of `path`
+fn parse_scene(path: &Path, source: &str) -> Result<Scene> {
+    let root: Table = toml::from_str(source).map_err(|source| SceneError::Syntax {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let parser = SceneParser {
+        path,
+        directory: path.parent().unwrap_or_else(|| Path::new("")),
+        warnings: RefCell::default(),
+    };
+    parser.parse(&root)
+}
+
+type Result<T> = std::result::Result<T, SceneError>;
+
+struct SceneParser<'a> {
+    path: &'a Path,
+    directory: &'a Path,
+    /// Collected while parsing and handed over with the scene
+    warnings: RefCell<Vec<String>>,
+}
+
+impl<'a> SceneParser<'a> {
+    fn parse(&self, root: &Table) -> Result<Scene> {
+        self.check_keys(
+            root,
+            "",
+            &[
+                "image",
+                "camera",
+                "environment",
+                "textures",
+                "materials",
+                "objects",
+                "lights",
+            ],
+        )?;
+
+        let image = match root.get("image") {
+            Some(value) => self.image(self.table(value, "image")?)?,
+            None => ImageSettings::default(),
+        };
+        let camera = match root.get("camera") {
+            Some(value) => self.camera(self.table(value, "camera")?)?,
+            None => return Err(self.error("camera", "missing camera table")),
+        };
+
+        let environment = match root.get("environment") {
+            Some(value) => self.environment(self.table(value, "environment")?)?,
+            None => Environment::default(),
+        };
+
+        let mut textures = HashMap::new();
+        if let Some(value) = root.get("textures") {
+            for (name, texture) in self.table(value, "textures")?.iter() {
+                let key = join("textures", name);
+                textures.insert(
+                    name.as_str(),
+                    self.texture(self.table(texture, &key)?, &key)?,
+                );
+            }
+        }
+
+        let mut materials = HashMap::new();
+        if let Some(value) = root.get("materials") {
+            for (name, material) in self.table(value, "materials")?.iter() {
+                let key = join("materials", name);
+                materials.insert(
+                    name.as_str(),
+                    self.material(self.table(material, &key)?, &key, &textures)?,
+                );
+            }
+        }
+
+        let mut world = HittableList::default();
+        if let Some(value) = root.get("objects") {
+            let objects = value
+                .as_array()
+                .ok_or_else(|| self.error("objects", "expected an array of tables"))?;
+            for (index, object) in objects.iter().enumerate() {
+                let key = format!("objects[{}]", index);
+                self.object(self.table(object, &key)?, &key, &materials, &mut world)?;
+            }
+        }
+
+        let mut lights = Vec::new();
+        if let Some(value) = root.get("lights") {
+            let tables = value
+                .as_array()
+                .ok_or_else(|| self.error("lights", "expected an array of tables"))?;
+            for (index, light) in tables.iter().enumerate() {
+                let key = format!("lights[{}]", index);
+                lights.push(self.light(self.table(light, &key)?, &key)?);
+            }
+        }
+
+        Ok(Scene {
+            image,
+            camera,
+            world,
+            environment,
+            lights,
+            warnings: self.warnings.take(),
+        })
+    }
+
+    fn image(&self, table: &Table) -> Result<ImageSettings> {
+        let key = "image";
+        self.check_keys(
+            table,
+            key,
+            &[
+                "width",
+                "height",
+                "aspect_ratio",
+                "samples_per_pixel",
+                "max_depth",
+            ],
+        )?;
+        let defaults = ImageSettings::default();
+
+        let width = self.count(table, key, "width")?.unwrap_or(defaults.width);
+        let aspect_ratio = match (
+            self.count(table, key, "height")?,
+            self.float(table, key, "aspect_ratio")?,
+        ) {
+            (Some(_), Some(_)) => {
+                return Err(self.error(
+                    &join(key, "height"),
+                    "height and aspect_ratio are mutually exclusive",
+                ))
+            }
+            (Some(height), None) => width as FloatType / height as FloatType,
+            (None, Some(aspect_ratio)) if aspect_ratio > 0.0 => aspect_ratio,
+            (None, Some(_)) => {
+                return Err(self.error(&join(key, "aspect_ratio"), "must be positive"))
+            }
+            (None, None) => defaults.aspect_ratio,
+        };
+
+        Ok(ImageSettings {
+            width,
+            aspect_ratio,
+            samples_per_pixel: self
+                .count(table, key, "samples_per_pixel")?
+                .unwrap_or(defaults.samples_per_pixel),
+            max_depth: self
+                .count(table, key, "max_depth")?
+                .unwrap_or(defaults.max_depth),
+        })
+    }
+
+    fn camera(&self, table: &Table) -> Result<CameraSettings> {
+        let key = "camera";
+        self.check_keys(
+            table,
+            key,
+            &[
+                "look_from",
+                "look_at",
+                "view_up",
+                "vertical_fov",
+                "aperture",
+                "focus_distance",
+            ],
+        )?;
+
+        let look_from = self.required(self.vector(table, key, "look_from")?, key, "look_from")?;
+        let look_at = self.required(self.vector(table, key, "look_at")?, key, "look_at")?;
+        Ok(CameraSettings {
+            look_from,
+            look_at,
+            view_up: self
+                .vector(table, key, "view_up")?
+                .unwrap_or_else(|| Vector3::new(0.0, 1.0, 0.0)),
+            vertical_fov: self.float(table, key, "vertical_fov")?.unwrap_or(40.0),
+            aperture: self.float(table, key, "aperture")?.unwrap_or(0.0),
+            focus_distance: self
+                .float(table, key, "focus_distance")?
+                .unwrap_or_else(|| (look_from - look_at).length()),
+        })
+    }
+
+    fn environment(&self, table: &Table) -> Result<Environment> {
+        let key = "environment";
+        let environment_type = self.required(self.string(table, key, "type")?, key, "type")?;
+        match environment_type {
+            "black" => {
+                self.check_keys(table, key, &["type"])?;
+                Ok(Environment::Black)
+            }
+            "solid" => {
+                self.check_keys(table, key, &["type", "color"])?;
+                let color = self.required(self.vector(table, key, "color")?, key, "color")?;
+                Ok(Environment::Solid(color))
+            }
+            "gradient" => {
+                self.check_keys(table, key, &["type", "bottom", "top"])?;
+                let bottom = self.required(self.vector(table, key, "bottom")?, key, "bottom")?;
+                let top = self.required(self.vector(table, key, "top")?, key, "top")?;
+                Ok(Environment::Gradient { bottom, top })
+            }
+            "image" => {
+                self.check_keys(table, key, &["type", "path", "rotation", "intensity"])?;
+                let image_path = self.directory.join(self.required(
+                    self.string(table, key, "path")?,
+                    key,
+                    "path",
+                )?);
+                let image = read_image(&image_path).map_err(|source| SceneError::Image {
+                    path: self.path.to_path_buf(),
+                    key: join(key, "path"),
+                    source,
+                })?;
+                let rotation = self.float(table, key, "rotation")?.unwrap_or(0.0);
+                let intensity = self.float(table, key, "intensity")?.unwrap_or(1.0);
+                if intensity < 0.0 {
+                    return Err(self.error(&join(key, "intensity"), "must not be negative"));
+                }
+                Ok(Environment::Map(EnvironmentMap::new(
+                    image, rotation, intensity,
+                )))
+            }
+            "sky" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &[
+                        "type",
+                        "sun_direction",
+                        "turbidity",
+                        "ground_albedo",
+                        "sun_size",
+                        "intensity",
+                    ],
+                )?;
+                let sun_direction = self.direction(table, key, "sun_direction")?;
+                if sun_direction.y <= 0.0 {
+                    return Err(self.error(
+                        &join(key, "sun_direction"),
+                        "must point above the horizon (positive y)",
+                    ));
+                }
+                let turbidity = self.float(table, key, "turbidity")?.unwrap_or(3.0);
+                if !(1.7..=10.0).contains(&turbidity) {
+                    return Err(self.error(&join(key, "turbidity"), "must be between 1.7 and 10"));
+                }
+                let ground_albedo = self
+                    .vector(table, key, "ground_albedo")?
+                    .unwrap_or_else(|| Color::new_eq(0.3));
+                let sun_size = self.float(table, key, "sun_size")?.unwrap_or(SUN_DIAMETER);
+                if !(sun_size > 0.0 && sun_size <= 90.0) {
+                    return Err(
+                        self.error(&join(key, "sun_size"), "must be between 0 and 90 degrees")
+                    );
+                }
+                let intensity = self.float(table, key, "intensity")?.unwrap_or(1.0);
+                if intensity < 0.0 {
+                    return Err(self.error(&join(key, "intensity"), "must not be negative"));
+                }
+                Ok(Environment::Sky(Sky::new(
+                    sun_direction,
+                    turbidity,
+                    ground_albedo,
+                    sun_size,
+                    intensity,
+                )))
+            }
+            _ => Err(self.error(
+                &join(key, "type"),
+                format!(
+                    "unknown environment type '{}', expected black, solid, gradient, image or sky",
+                    environment_type
+                ),
+            )),
+        }
+    }
+
+    fn light(&self, table: &Table, key: &str) -> Result<PunctualLight> {
+        let light_type = self.required(self.string(table, key, "type")?, key, "type")?;
+        match light_type {
+            "point" => {
+                self.check_keys(table, key, &["type", "position", "intensity"])?;
+                let position =
+                    self.required(self.vector(table, key, "position")?, key, "position")?;
+                let intensity =
+                    self.required(self.vector(table, key, "intensity")?, key, "intensity")?;
+                Ok(PunctualLight::point(position, intensity))
+            }
+            "spot" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &[
+                        "type",
+                        "position",
+                        "direction",
+                        "intensity",
+                        "inner_angle",
+                        "outer_angle",
+                    ],
+                )?;
+                let position =
+                    self.required(self.vector(table, key, "position")?, key, "position")?;
+                let direction = self.direction(table, key, "direction")?;
+                let intensity =
+                    self.required(self.vector(table, key, "intensity")?, key, "intensity")?;
+                let outer_angle =
+                    self.required(self.float(table, key, "outer_angle")?, key, "outer_angle")?;
+                if !(0.0..=180.0).contains(&outer_angle) {
+                    return Err(self.error(
+                        &join(key, "outer_angle"),
+                        "must be between 0 and 180 degrees",
+                    ));
+                }
+                let inner_angle = self
+                    .float(table, key, "inner_angle")?
+                    .unwrap_or(outer_angle);
+                if !(0.0..=outer_angle).contains(&inner_angle) {
+                    return Err(self.error(
+                        &join(key, "inner_angle"),
+                        "must be between 0 and outer_angle",
+                    ));
+                }
+                Ok(PunctualLight::spot(
+                    position,
+                    direction,
+                    intensity,
+                    inner_angle,
+                    outer_angle,
+                ))
+            }
+            "directional" => {
+                self.check_keys(table, key, &["type", "direction", "irradiance"])?;
+                let direction = self.direction(table, key, "direction")?;
+                let irradiance =
+                    self.required(self.vector(table, key, "irradiance")?, key, "irradiance")?;
+                Ok(PunctualLight::directional(direction, irradiance))
+            }
+            _ => Err(self.error(
+                &join(key, "type"),
+                format!(
+                    "unknown light type '{}', expected point, spot or directional",
+                    light_type
+                ),
+            )),
+        }
+    }
+
+    /// Required non-zero direction vector
+    fn direction(&self, table: &Table, key: &str, name: &str) -> Result<Vector3> {
+        let direction = self.required(self.vector(table, key, name)?, key, name)?;
+        if direction.is_near_zero() {
+            return Err(self.error(&join(key, name), "must not be zero"));
+        }
+        Ok(direction)
+    }
+
+    fn texture(&self, table: &Table, key: &str) -> Result<Arc<dyn Texture>> {
+        let texture_type = self.required(self.string(table, key, "type")?, key, "type")?;
+        match texture_type {
+            "constant" => {
+                self.check_keys(table, key, &["type", "color"])?;
+                let color = self.required(self.vector(table, key, "color")?, key, "color")?;
+                Ok(color.into())
+            }
+            "checker" => {
+                self.check_keys(table, key, &["type", "even", "odd", "scale", "space"])?;
+                let even = self.required(self.vector(table, key, "even")?, key, "even")?;
+                let odd = self.required(self.vector(table, key, "odd")?, key, "odd")?;
+                let scale = self.float(table, key, "scale")?.unwrap_or(1.0);
+                if scale <= 0.0 {
+                    return Err(self.error(&join(key, "scale"), "must be positive"));
+                }
+                let space = match self.string(table, key, "space")?.unwrap_or("uv") {
+                    "uv" => CheckerSpace::Uv,
+                    "world" => CheckerSpace::World,
+                    space => {
+                        return Err(self.error(
+                            &join(key, "space"),
+                            format!("unknown checker space '{}', expected uv or world", space),
+                        ))
+                    }
+                };
+                Ok(Arc::new(Checker::new(
+                    even.into(),
+                    odd.into(),
+                    scale,
+                    space,
+                )))
+            }
+            "image" => {
+                self.check_keys(table, key, &["type", "path", "wrap", "color_space"])?;
+                let image_path = self.directory.join(self.required(
+                    self.string(table, key, "path")?,
+                    key,
+                    "path",
+                )?);
+                // Normal and height maps store data, not sRGB colors
+                let read = match self.string(table, key, "color_space")?.unwrap_or("srgb") {
+                    "srgb" => read_image,
+                    "linear" => read_image_data,
+                    color_space => {
+                        return Err(self.error(
+                            &join(key, "color_space"),
+                            format!(
+                                "unknown color space '{}', expected srgb or linear",
+                                color_space
+                            ),
+                        ))
+                    }
+                };
+                let image = read(&image_path).map_err(|source| SceneError::Image {
+                    path: self.path.to_path_buf(),
+                    key: join(key, "path"),
+                    source,
+                })?;
+                let wrap = match self.string(table, key, "wrap")?.unwrap_or("repeat") {
+                    "repeat" => WrapMode::Repeat,
+                    "mirror" => WrapMode::Mirror,
+                    "clamp" => WrapMode::Clamp,
+                    wrap => {
+                        return Err(self.error(
+                            &join(key, "wrap"),
+                            format!(
+                                "unknown wrap mode '{}', expected repeat, mirror or clamp",
+                                wrap
+                            ),
+                        ))
+                    }
+                };
+                Ok(Arc::new(ImageTexture::new(image, wrap)))
+            }
+            "noise" | "turbulence" | "marble" | "wood" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &["type", "scale", "octaves", "low", "high", "seed"],
+                )?;
+                let pattern = match texture_type {
+                    "noise" => NoisePattern::Noise,
+                    "turbulence" => NoisePattern::Turbulence,
+                    "marble" => NoisePattern::Marble,
+                    _ => NoisePattern::Wood,
+                };
+                let scale = self.float(table, key, "scale")?.unwrap_or(1.0);
+                if scale <= 0.0 {
+                    return Err(self.error(&join(key, "scale"), "must be positive"));
+                }
+                let octaves = self.count(table, key, "octaves")?.unwrap_or(7);
+                let low = self
+                    .vector(table, key, "low")?
+                    .unwrap_or_else(|| Color::new_eq(0.0));
+                let high = self
+                    .vector(table, key, "high")?
+                    .unwrap_or_else(|| Color::new_eq(1.0));
+                let seed = match table.get("seed") {
+                    Some(value) => match value.as_integer() {
+                        Some(seed) if seed >= 0 => seed as u64,
+                        _ => {
+                            return Err(self.error(
+                                &join(key, "seed"),
+                                "expected a non-negative integer",
+                            ))
+                        }
+                    },
+                    None => 0,
+                };
+                Ok(Arc::new(NoiseTexture::new(
+                    pattern, scale, octaves, low, high, seed,
+                )))
+            }
+            _ => Err(self.error(
+                &join(key, "type"),
+                format!(
+                    "unknown texture type '{}', expected constant, checker, image, noise, turbulence, marble or wood",
+                    texture_type
+                ),
+            )),
+        }
+    }
+
+    /// Material of any type, optionally with a normal or bump map
+    fn material(
+        &self,
+        table: &Table,
+        key: &str,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+    ) -> Result<Material> {
+        const MAP_KEYS: [&str; 4] = ["normal_map", "normal_strength", "bump_map", "bump_scale"];
+        let mut base_table = table.clone();
+        for name in MAP_KEYS {
+            base_table.remove(name);
+        }
+        let base = self.base_material(&base_table, key, textures)?;
+
+        let texture = |name: &str| match self.string(table, key, name)? {
+            Some(texture) => match textures.get(texture) {
+                Some(texture) => Ok(Some(texture.clone())),
+                None => Err(self.error(&join(key, name), format!("unknown texture '{}'", texture))),
+            },
+            None => Ok(None),
+        };
+        let map = match (texture("normal_map")?, texture("bump_map")?) {
+            (Some(_), Some(_)) => {
+                return Err(self.error(&join(key, "bump_map"), "can't be combined with normal_map"))
+            }
+            (Some(normals), None) => NormalMap::Tangent {
+                normals,
+                strength: self.float(table, key, "normal_strength")?.unwrap_or(1.0),
+            },
+            (None, Some(height)) => NormalMap::Bump {
+                height,
+                scale: self.float(table, key, "bump_scale")?.unwrap_or(1.0),
+            },
+            (None, None) => {
+                for (name, map) in [
+                    ("normal_strength", "normal_map"),
+                    ("bump_scale", "bump_map"),
+                ] {
+                    if table.contains_key(name) {
+                        return Err(self.error(&join(key, name), format!("needs a {}", map)));
+                    }
+                }
+                return Ok(base);
+            }
+        };
+        Ok(Material::NormalMapped {
+            base: Box::new(base),
+            map: Arc::new(map),
+        })
+    }
+
+    fn base_material(
+        &self,
+        table: &Table,
+        key: &str,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+    ) -> Result<Material> {
+        let material_type = self.required(self.string(table, key, "type")?, key, "type")?;
+        match material_type {
+            "lambertian" => {
+                self.check_keys(table, key, &["type", "albedo"])?;
+                let albedo = self.required(
+                    self.color_texture(table, key, "albedo", textures)?,
+                    key,
+                    "albedo",
+                )?;
+                Ok(Material::Lambertian(albedo))
+            }
+            "metal" => {
+                self.check_keys(table, key, &["type", "albedo", "fuzz"])?;
+                let albedo = self.required(
+                    self.color_texture(table, key, "albedo", textures)?,
+                    key,
+                    "albedo",
+                )?;
+                let fuzz = self.float(table, key, "fuzz")?.unwrap_or(0.0);
+                Ok(Material::Metal(Metal::new(albedo, fuzz)))
+            }
+            "dielectric" => {
+                self.check_keys(table, key, &["type", "index_of_refraction"])?;
+                let index_of_refraction = self
+                    .float(table, key, "index_of_refraction")?
+                    .unwrap_or(1.5);
+                Ok(Material::Dielectric(index_of_refraction))
+            }
+            "conductor" => {
+                self.check_keys(table, key, &["type", "metal", "eta", "k", "roughness"])?;
+                let (eta, k) = match self.string(table, key, "metal")? {
+                    Some(name) => {
+                        if table.contains_key("eta") || table.contains_key("k") {
+                            return Err(self.error(
+                                &join(key, "metal"),
+                                "can't be combined with eta and k",
+                            ));
+                        }
+                        named_metal(name).ok_or_else(|| {
+                            self.error(
+                                &join(key, "metal"),
+                                format!(
+                                    "unknown metal '{}', expected one of: {}",
+                                    name,
+                                    metal_names().collect::<Vec<_>>().join(", ")
+                                ),
+                            )
+                        })?
+                    }
+                    None => (
+                        self.required(self.vector(table, key, "eta")?, key, "eta")?,
+                        self.required(self.vector(table, key, "k")?, key, "k")?,
+                    ),
+                };
+                let roughness = self.roughness(table, key)?;
+                Ok(Material::Conductor(Conductor::new(eta, k, roughness)))
+            }
+            "rough_dielectric" => {
+                self.check_keys(table, key, &["type", "index_of_refraction", "roughness"])?;
+                let index_of_refraction = self
+                    .float(table, key, "index_of_refraction")?
+                    .unwrap_or(1.5);
+                if index_of_refraction <= 0.0 {
+                    return Err(self.error(
+                        &join(key, "index_of_refraction"),
+                        "must be positive",
+                    ));
+                }
+                let roughness = self.roughness(table, key)?;
+                Ok(Material::RoughDielectric(RoughDielectric::new(
+                    index_of_refraction,
+                    roughness,
+                )))
+            }
+            "principled" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &[
+                        "type",
+                        "base_color",
+                        "metallic",
+                        "roughness",
+                        "specular",
+                        "specular_tint",
+                        "sheen",
+                        "clearcoat",
+                        "transmission",
+                        "index_of_refraction",
+                    ],
+                )?;
+                let defaults = Principled::default();
+                let unit = |name| {
+                    self.scalar_texture(
+                        table,
+                        key,
+                        name,
+                        textures,
+                        |value| (0.0..=1.0).contains(&value),
+                        "must be between 0 and 1",
+                    )
+                };
+                Ok(Material::Principled(Principled {
+                    base_color: self
+                        .color_texture(table, key, "base_color", textures)?
+                        .unwrap_or(defaults.base_color),
+                    metallic: unit("metallic")?.unwrap_or(defaults.metallic),
+                    roughness: unit("roughness")?.unwrap_or(defaults.roughness),
+                    specular: unit("specular")?.unwrap_or(defaults.specular),
+                    specular_tint: unit("specular_tint")?.unwrap_or(defaults.specular_tint),
+                    sheen: unit("sheen")?.unwrap_or(defaults.sheen),
+                    clearcoat: unit("clearcoat")?.unwrap_or(defaults.clearcoat),
+                    transmission: unit("transmission")?.unwrap_or(defaults.transmission),
+                    index_of_refraction: self
+                        .scalar_texture(
+                            table,
+                            key,
+                            "index_of_refraction",
+                            textures,
+                            |value| value > 0.0,
+                            "must be positive",
+                        )?
+                        .unwrap_or(defaults.index_of_refraction),
+                }))
+            }
+            "coated" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &[
+                        "type",
+                        "base",
+                        "index_of_refraction",
+                        "roughness",
+                        "thickness",
+                        "absorption",
+                    ],
+                )?;
+                let base_key = join(key, "base");
+                let base = self.required(table.get("base"), key, "base")?;
+                let base = self.material(self.table(base, &base_key)?, &base_key, textures)?;
+                let index_of_refraction = self
+                    .float(table, key, "index_of_refraction")?
+                    .unwrap_or(1.5);
+                if index_of_refraction <= 0.0 {
+                    return Err(self.error(
+                        &join(key, "index_of_refraction"),
+                        "must be positive",
+                    ));
+                }
+                let thickness = self.float(table, key, "thickness")?.unwrap_or(1.0);
+                if thickness < 0.0 {
+                    return Err(self.error(&join(key, "thickness"), "must not be negative"));
+                }
+                let absorption = self
+                    .vector(table, key, "absorption")?
+                    .unwrap_or_else(|| Color::new_eq(0.0));
+                if absorption.x < 0.0 || absorption.y < 0.0 || absorption.z < 0.0 {
+                    return Err(self.error(&join(key, "absorption"), "must not be negative"));
+                }
+                let coat = Coat::new(
+                    index_of_refraction,
+                    self.roughness(table, key)?,
+                    thickness,
+                    absorption,
+                );
+                Ok(match base {
+                    // A hit has one shading normal, so the map of the base tilts the coat too
+                    Material::NormalMapped { base, map } => Material::NormalMapped {
+                        base: Box::new(Material::Coated { base, coat }),
+                        map,
+                    },
+                    base => Material::Coated {
+                        base: Box::new(base),
+                        coat,
+                    },
+                })
+            }
+            "vertex_color" => {
+                self.check_keys(table, key, &["type"])?;
+                Ok(Material::VertexColor)
+            }
+            "isotropic" => {
+                self.check_keys(table, key, &["type", "albedo"])?;
+                let albedo = self.required(
+                    self.color_texture(table, key, "albedo", textures)?,
+                    key,
+                    "albedo",
+                )?;
+                Ok(Material::Isotropic(albedo))
+            }
+            "diffuse_light" => {
+                self.check_keys(table, key, &["type", "emit"])?;
+                let emit = self.required(self.vector(table, key, "emit")?, key, "emit")?;
+                Ok(Material::DiffuseLight(emit))
+            }
+            _ => Err(self.error(
+                &join(key, "type"),
+                format!(
+                    "unknown material type '{}', expected lambertian, metal, dielectric, conductor, rough_dielectric, principled, coated, isotropic, vertex_color or diffuse_light",
+                    material_type
+                ),
+            )),
+        }
+    }
+
+    fn object(
+        &self,
+        table: &Table,
+        key: &str,
+        materials: &HashMap<&str, Material>,
+        world: &mut HittableList,
+    ) -> Result<()> {
+        let material = |required: bool| -> Result<Option<Material>> {
+            match self.string(table, key, "material")? {
+                Some(name) => match materials.get(name) {
+                    Some(material) => Ok(Some(material.clone())),
+                    None => Err(self.error(
+                        &join(key, "material"),
+                        format!("unknown material '{}'", name),
+                    )),
+                },
+                None if required => Err(self.error(&join(key, "material"), "missing value")),
+                None => Ok(None),
+            }
+        };
+
+        let object_type = self.required(self.string(table, key, "type")?, key, "type")?;
+        match object_type {
+            "sphere" => {
+                self.check_keys(table, key, &["type", "center", "radius", "material"])?;
+                let center = self.required(self.vector(table, key, "center")?, key, "center")?;
+                let radius = self.required(self.float(table, key, "radius")?, key, "radius")?;
+                world.add(Box::new(Sphere::new(
+                    center,
+                    radius,
+                    material(true)?.unwrap(),
+                )));
+            }
+            "triangle" => {
+                self.check_keys(table, key, &["type", "vertices", "material"])?;
+                let vertices_key = join(key, "vertices");
+                let vertices = self
+                    .required(table.get("vertices"), key, "vertices")?
+                    .as_array()
+                    .filter(|vertices| vertices.len() == 3)
+                    .ok_or_else(|| self.error(&vertices_key, "expected an array of 3 points"))?
+                    .iter()
+                    .enumerate()
+                    .map(|(index, vertex)| {
+                        self.vector_value(vertex, &format!("{}[{}]", vertices_key, index))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                world.add(Box::new(Triangle::new(
+                    vertices[0],
+                    vertices[1],
+                    vertices[2],
+                    material(true)?.unwrap(),
+                )));
+            }
+            "obj" | "ply" => {
+                self.check_keys(
+                    table,
+                    key,
+                    &["type", "path", "material", "scale", "translate"],
+                )?;
+                let model_path = self.directory.join(self.required(
+                    self.string(table, key, "path")?,
+                    key,
+                    "path",
+                )?);
+                let default_material = material(false)?
+                    .unwrap_or_else(|| Material::Lambertian(Color::new_eq(0.8).into()));
+                let scale = self.float(table, key, "scale")?.unwrap_or(1.0);
+                if scale <= 0.0 {
+                    return Err(self.error(&join(key, "scale"), "must be positive"));
+                }
+                let translate = self
+                    .vector(table, key, "translate")?
+                    .unwrap_or_else(|| Vector3::new_eq(0.0));
+
+                let meshes = if object_type == "obj" {
+                    let model = load_obj(&model_path, &default_material).map_err(|source| {
+                        SceneError::Obj {
+                            path: self.path.to_path_buf(),
+                            key: join(key, "path"),
+                            source: Box::new(source),
+                        }
+                    })?;
+                    for warning in &model.warnings {
+                        self.warn(&join(key, "path"), warning);
+                    }
+                    model.meshes
+                } else {
+                    vec![load_ply(&model_path, &default_material).map_err(|source| {
+                        SceneError::Ply {
+                            path: self.path.to_path_buf(),
+                            key: join(key, "path"),
+                            source: Box::new(source),
+                        }
+                    })?]
+                };
+                for mesh in meshes {
+                    world.add_mesh(transform_mesh(mesh, scale, translate));
+                }
+            }
+            "constant_medium" => {
+                self.check_keys(table, key, &["type", "boundary", "density", "material"])?;
+                let density = self.required(self.float(table, key, "density")?, key, "density")?;
+                if density <= 0.0 {
+                    return Err(self.error(&join(key, "density"), "must be positive"));
+                }
+                let phase_function = material(true)?.unwrap();
+                if !matches!(phase_function, Material::Isotropic(_)) {
+                    return Err(self.error(
+                        &join(key, "material"),
+                        "must be an isotropic material",
+                    ));
+                }
+
+                let boundary_key = join(key, "boundary");
+                let mut boundary_table = self
+                    .table(
+                        self.required(table.get("boundary"), key, "boundary")?,
+                        &boundary_key,
+                    )?
+                    .clone();
+                if boundary_table.contains_key("material") {
+                    return Err(self.error(
+                        &join(&boundary_key, "material"),
+                        "the boundary isn't rendered, add a separate object for a visible surface",
+                    ));
+                }
+                if self.string(&boundary_table, &boundary_key, "type")? == Some("constant_medium") {
+                    return Err(self.error(
+                        &join(&boundary_key, "type"),
+                        "media can't be nested",
+                    ));
+                }
+                // The boundary is never shaded, it only needs some material to be parsed
+                boundary_table.insert("material".to_string(), table["material"].clone());
+                let mut boundary = HittableList::default();
+                self.object(&boundary_table, &boundary_key, materials, &mut boundary)?;
+
+                // Mesh boundaries are many triangles, intersected twice per ray. Like for all
+                // media the mesh has to be closed and convex, see `ConstantMedium`.
+                world.add(Box::new(ConstantMedium::new(
+                    Box::new(BvhNode::with_split_method(boundary, SplitMethod::default())),
+                    density,
+                    phase_function,
+                )));
+            }
+            _ => {
+                return Err(self.error(
+                    &join(key, "type"),
+                    format!(
+                        "unknown object type '{}', expected sphere, triangle, obj, ply or constant_medium",
+                        object_type
+                    ),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn error(&self, key: &str, message: impl Into<String>) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+
+    fn warn(&self, key: &str, message: impl fmt::Display) {
+        self.warnings
+            .borrow_mut()
+            .push(format!("{}: {}: {}", self.path.display(), key, message));
+    }
+
+    /// Rejects keys that aren't in `allowed`, which are most likely typos
+    fn check_keys(&self, table: &Table, key: &str, allowed: &[&str]) -> Result<()> {
+        match table.keys().find(|name| !allowed.contains(&name.as_str())) {
+            Some(name) => Err(self.error(
+                &join(key, name),
+                format!("unknown key, expected one of: {}", allowed.join(", ")),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn required<T>(&self, value: Option<T>, key: &str, name: &str) -> Result<T> {
+        value.ok_or_else(|| self.error(&join(key, name), "missing value"))
+    }
+
+    fn table<'v>(&self, value: &'v Value, key: &str) -> Result<&'v Table> {
+        value
+            .as_table()
+            .ok_or_else(|| self.error(key, format!("expected a table, found {}", value.type_str())))
+    }
+
+    fn string<'v>(&self, table: &'v Table, key: &str, name: &str) -> Result<Option<&'v str>> {
+        table
+            .get(name)
+            .map(|value| {
+                value.as_str().ok_or_else(|| {
+                    self.error(
+                        &join(key, name),
+                        format!("expected a string, found {}", value.type_str()),
+                    )
+                })
+            })
+            .transpose()
+    }
+
+    fn float(&self, table: &Table, key: &str, name: &str) -> Result<Option<FloatType>> {
+        table
+            .get(name)
+            .map(|value| self.float_value(value, &join(key, name)))
+            .transpose()
+    }
+
+    /// Positive integer, e.g. a size or sample count
+    fn count(&self, table: &Table, key: &str, name: &str) -> Result<Option<usize>> {
+        table
+            .get(name)
+            .map(|value| match value.as_integer() {
+                Some(count) if count > 0 => Ok(count as usize),
+                _ => Err(self.error(&join(key, name), "expected a positive integer")),
+            })
+            .transpose()
+    }
+
+    /// Optional `roughness` in [0,1], smooth by default
+    fn roughness(&self, table: &Table, key: &str) -> Result<FloatType> {
+        let roughness = self.float(table, key, "roughness")?.unwrap_or(0.0);
+        if !(0.0..=1.0).contains(&roughness) {
+            return Err(self.error(&join(key, "roughness"), "must be between 0 and 1"));
+        }
+        Ok(roughness)
+    }
+
+    /// Color given as array or as name of a texture
+    fn color_texture(
+        &self,
+        table: &Table,
+        key: &str,
+        name: &str,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+    ) -> Result<Option<Arc<dyn Texture>>> {
+        match table.get(name) {
+            Some(Value::String(texture)) => match textures.get(texture.as_str()) {
+                Some(texture) => Ok(Some(texture.clone())),
+                None => Err(self.error(&join(key, name), format!("unknown texture '{}'", texture))),
+            },
+            Some(value) => Ok(Some(self.vector_value(value, &join(key, name))?.into())),
+            None => Ok(None),
+        }
+    }
+
+    /// Number given directly or as name of a texture, whose channel mean is used. Numbers
+    /// failing `is_valid` are reported with `requirement`.
+    fn scalar_texture(
+        &self,
+        table: &Table,
+        key: &str,
+        name: &str,
+        textures: &HashMap<&str, Arc<dyn Texture>>,
+        is_valid: impl Fn(FloatType) -> bool,
+        requirement: &str,
+    ) -> Result<Option<Arc<dyn Texture>>> {
+        match table.get(name) {
+            Some(Value::String(_)) => self.color_texture(table, key, name, textures),
+            Some(value) => {
+                let number = self.float_value(value, &join(key, name))?;
+                if !is_valid(number) {
+                    return Err(self.error(&join(key, name), requirement));
+                }
+                Ok(Some(Color::new_eq(number).into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn vector(&self, table: &Table, key: &str, name: &str) -> Result<Option<Vector3>> {
+        table
+            .get(name)
+            .map(|value| self.vector_value(value, &join(key, name)))
+            .transpose()
+    }
+
+    fn float_value(&self, value: &Value, key: &str) -> Result<FloatType> {
+        match value {
+            Value::Float(value) => Ok(*value as FloatType),
+            Value::Integer(value) => Ok(*value as FloatType),
+            _ => Err(self.error(
+                key,
+                format!("expected a number, found {}", value.type_str()),
+            )),
+        }
+    }
+
+    fn vector_value(&self, value: &Value, key: &str) -> Result<Point3> {
+        let components = value
+            .as_array()
+            .filter(|components| components.len() == 3)
+            .ok_or_else(|| self.error(key, "expected an array of 3 numbers"))?;
+        Ok(Vector3::new(
+            self.float_value(&components[0], &format!("{}[0]", key))?,
+            self.float_value(&components[1], &format!("{}[1]", key))?,
+            self.float_value(&components[2], &format!("{}[2]", key))?,
+        ))
+    }
+}
+
+fn join(key: &str, name: &str) -> String {
+    if key.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", key, name)
+    }
+}
+
+/// Uniformly scales the mesh around the origin, then moves it
+fn transform_mesh(mut mesh: TriangleMesh, scale: FloatType, translate: Vector3) -> TriangleMesh {
+    for position in mesh.positions.iter_mut() {
+        *position = scale * *position + translate;
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlook_from = [0, 0, 5]\nlook_at = [0, 0, 0]\n";
+
+    fn parse(source: &str) -> Result<Scene> {
+        parse_scene(Path::new("scenes/test.toml"), source)
+    }
+
+    fn error_message(source: &str) -> String {
+        parse(source)
+            .err()
+            .expect("Parsing should have failed")
+            .to_string()
+    }
+
+    #[test]
+    fn minimal_scene() {
+        let scene = parse(&format!(
+            "{}\n[materials.white]\ntype = \"lambertian\"\nalbedo = [0.8, 0.8, 0.8]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"white\"\n",
+            CAMERA
+        ))
+        .unwrap_or_else(|error| panic!("{}", error));
+        assert_eq!(scene.world.objects.len(), 1);
+        assert!(scene.warnings.is_empty());
+    }
+
+    #[test]
+    fn example_scenes_load() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        for entry in fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                if let Err(error) = load_scene(&path) {
+                    panic!("{}", error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn missing_camera() {
+        assert_eq!(
+            error_message("[image]\nwidth = 100\n"),
+            "scenes/test.toml: camera: missing camera table"
+        );
+    }
+
+    #[test]
+    fn missing_camera_value() {
+        assert_eq!(
+            error_message("[camera]\nlook_from = [0, 0, 5]\n"),
+            "scenes/test.toml: camera.look_at: missing value"
+        );
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert_eq!(
+            error_message(&format!("{}\n[image]\nwidht = 100\n", CAMERA)),
+            "scenes/test.toml: image.widht: unknown key, expected one of: \
+             width, height, aspect_ratio, samples_per_pixel, max_depth"
+        );
+        assert_eq!(
+            error_message(&format!("{}fov = 20\n", CAMERA)),
+            "scenes/test.toml: camera.fov: unknown key, expected one of: \
+             look_from, look_at, view_up, vertical_fov, aperture, focus_distance"
+        );
+    }
+
+    #[test]
+    fn wrong_type() {
+        let source = format!(
+            "{}\n[materials.white]\ntype = \"lambertian\"\nalbedo = [0.8, 0.8, 0.8]\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"white\"\n\n\
+             [[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = \"big\"\nmaterial = \"white\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "scenes/test.toml: objects[1].radius: expected a number, found string"
+        );
+    }
+
+    #[test]
+    fn wrong_vector_component() {
+        assert_eq!(
+            error_message("[camera]\nlook_from = [0, \"up\", 5]\nlook_at = [0, 0, 0]\n"),
+            "scenes/test.toml: camera.look_from[1]: expected a number, found string"
+        );
+    }
+
+    #[test]
+    fn undefined_material() {
+        let source = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"gold\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "scenes/test.toml: objects[0].material: unknown material 'gold'"
+        );
+    }
+
+    #[test]
+    fn undefined_texture() {
+        let source = format!(
+            "{}\n[materials.white]\ntype = \"lambertian\"\nalbedo = \"marble\"\n",
+            CAMERA
+        );
+        assert_eq!(
+            error_message(&source),
+            "scenes/test.toml: materials.white.albedo: unknown texture 'marble'"
+        );
+    }
+}
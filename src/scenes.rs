@@ -1,16 +1,58 @@
+use std::{error::Error, path::Path};
+
 use crate::{
+    environment::Environment,
     hittable::HittableList,
     material::{Material, Metal},
+    mesh::TriangleMesh,
+    obj::{load_obj, ObjError},
+    ply::{load_ply, PlyError},
+    scene::{CameraSettings, ImageSettings, Scene},
+    scene_file::load_scene,
     sphere::Sphere,
+    triangle::Triangle,
     utils::random_float,
-    vec::{Color, FloatType, Point3, PI},
+    vec::{Color, FloatType, Point3, Vector3},
 };
 
-#[allow(unused)]
-pub fn random_scene() -> HittableList {
+/// Scene that is compiled into the binary
+pub struct BuiltinScene {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub build: fn() -> Scene,
+}
+
+pub const BUILTIN_SCENES: &[BuiltinScene] = &[
+    BuiltinScene {
+        name: "random",
+        description: "Final scene of the book, ~480 random small spheres and three big ones",
+        build: random_scene,
+    },
+    BuiltinScene {
+        name: "three-balls",
+        description: "Diffuse, hollow glass and fuzzy metal ball on a yellow ground",
+        build: three_balls_scene,
+    },
+    BuiltinScene {
+        name: "triangles",
+        description: "Smooth shaded octahedron mesh and a metal ball on a triangle ground",
+        build: triangles_scene,
+    },
+    BuiltinScene {
+        name: "cornell-box",
+        description: "Closed Cornell box with two blocks, lit only by the ceiling light",
+        build: cornell_box_scene,
+    },
+];
+
+pub fn builtin_scene(name: &str) -> Option<&'static BuiltinScene> {
+    BUILTIN_SCENES.iter().find(|scene| scene.name == name)
+}
+
+pub fn random_scene() -> Scene {
     let mut world = HittableList::default();
 
-    let ground_material = Material::Lambertian(Color::new(0.5, 0.5, 0.5));
+    let ground_material = Material::Lambertian(Color::new(0.5, 0.5, 0.5).into());
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -34,7 +76,7 @@ pub fn random_scene() -> HittableList {
                         world.add(Box::new(Sphere::new(
                             center,
                             0.2,
-                            Material::Lambertian(albedo),
+                            Material::Lambertian(albedo.into()),
                         )));
                     }
                     x if x < 0.95 => {
@@ -44,7 +86,7 @@ pub fn random_scene() -> HittableList {
                         world.add(Box::new(Sphere::new(
                             center,
                             0.2,
-                            Material::Metal(Metal::new(albedo, fuzz)),
+                            Material::Metal(Metal::new(albedo.into(), fuzz)),
                         )));
                     }
                     _ => {
@@ -67,35 +109,43 @@ pub fn random_scene() -> HittableList {
         material1,
     )));
 
-    let material2 = Material::Lambertian(Color::new(0.4, 0.2, 0.1));
+    let material2 = Material::Lambertian(Color::new(0.4, 0.2, 0.1).into());
     world.add(Box::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material2,
     )));
 
-    let material3 = Material::Metal(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    let material3 = Material::Metal(Metal::new(Color::new(0.7, 0.6, 0.5).into(), 0.0));
     world.add(Box::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
         material3,
     )));
 
-    world
+    Scene {
+        image: ImageSettings::default(),
+        camera: CameraSettings::default(),
+        world,
+        environment: Environment::default(),
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    }
 }
 
-#[allow(unused)]
-pub fn three_balls_scene() -> HittableList {
-    let r = (PI / 4.0).cos();
+pub fn three_balls_scene() -> Scene {
     let mut world = HittableList::default();
 
-    let material_ground = Material::Lambertian(Color::new(0.8, 0.8, 0.0));
-    let material_center = Material::Lambertian(Color::new(0.1, 0.2, 0.5));
-    let material_left = Material::Metal(Metal::new(Color::new(0.8, 0.8, 0.8), 0.3));
-    let material_right = Material::Metal(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0));
-    //let material_center = Material::Dielectric(1.5);
+    let material_ground = Material::Lambertian(Color::new(0.8, 0.8, 0.0).into());
+    let material_center = Material::Lambertian(Color::new(0.1, 0.2, 0.5).into());
+    let material_right = Material::Metal(Metal::new(Color::new(0.8, 0.6, 0.2).into(), 1.0));
     let material_left = Material::Dielectric(1.5);
 
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -100.5, -1.0),
+        100.0,
+        material_ground,
+    )));
     world.add(Box::new(Sphere::new(
         Point3::new(0.0, 0.0, -1.0),
         0.5,
@@ -116,5 +166,281 @@ pub fn three_balls_scene() -> HittableList {
         0.50,
         material_right,
     )));
-    world
+
+    let look_from = Point3::new(3.0, 3.0, 2.0);
+    let look_at = Point3::new(0.0, 0.0, -1.0);
+    Scene {
+        image: ImageSettings {
+            width: 400,
+            aspect_ratio: 16.0 / 9.0,
+            samples_per_pixel: 100,
+            max_depth: 50,
+        },
+        camera: CameraSettings {
+            look_from,
+            look_at,
+            view_up: Vector3::new(0.0, 1.0, 0.0),
+            vertical_fov: 20.0,
+            aperture: 2.0,
+            focus_distance: (look_from - look_at).length(),
+        },
+        world,
+        environment: Environment::default(),
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
+
+pub fn triangles_scene() -> Scene {
+    let mut world = HittableList::default();
+
+    // Ground quad made of two triangles
+    let material_ground = Material::Lambertian(Color::new(0.5, 0.5, 0.5).into());
+    let corners = [
+        Point3::new(-10.0, 0.0, 10.0),
+        Point3::new(10.0, 0.0, 10.0),
+        Point3::new(10.0, 0.0, -10.0),
+        Point3::new(-10.0, 0.0, -10.0),
+    ];
+    world.add(Box::new(Triangle::new(
+        corners[0],
+        corners[1],
+        corners[2],
+        material_ground.clone(),
+    )));
+    world.add(Box::new(Triangle::new(
+        corners[0],
+        corners[2],
+        corners[3],
+        material_ground,
+    )));
+
+    // Octahedron with vertex normals pointing away from its center, so it shades like a sphere
+    let center = Point3::new(0.0, 1.0, 0.0);
+    let directions = vec![
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(-1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+        Point3::new(0.0, -1.0, 0.0),
+        Point3::new(0.0, 0.0, 1.0),
+        Point3::new(0.0, 0.0, -1.0),
+    ];
+    let positions = directions.iter().map(|&d| center + d).collect();
+    let indices = vec![
+        [0, 2, 4],
+        [4, 2, 1],
+        [1, 2, 5],
+        [5, 2, 0],
+        [4, 3, 0],
+        [1, 3, 4],
+        [5, 3, 1],
+        [0, 3, 5],
+    ];
+    let mesh = TriangleMesh::new(
+        positions,
+        directions,
+        Vec::new(),
+        Vec::new(),
+        indices,
+        Material::Lambertian(Color::new(0.1, 0.2, 0.5).into()),
+    );
+    world.add_mesh(mesh);
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, -2.5),
+        1.0,
+        Material::Metal(Metal::new(Color::new(0.8, 0.6, 0.2).into(), 0.0)),
+    )));
+
+    Scene {
+        image: ImageSettings::default(),
+        camera: CameraSettings::default(),
+        world,
+        environment: Environment::default(),
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
+
+/// Adds the parallelogram with corner `q` and edges `u` and `v` as two triangles
+fn add_quad(world: &mut HittableList, q: Point3, u: Vector3, v: Vector3, material: Material) {
+    world.add(Box::new(Triangle::new(
+        q,
+        q + u,
+        q + u + v,
+        material.clone(),
+    )));
+    world.add(Box::new(Triangle::new(q, q + u + v, q + v, material)));
+}
+
+/// Adds the six faces of a box that is rotated around the y axis at its `min` corner
+fn add_block(
+    world: &mut HittableList,
+    min: Point3,
+    size: Vector3,
+    rotation_degrees: FloatType,
+    material: Material,
+) {
+    let (sin, cos) = rotation_degrees.to_radians().sin_cos();
+    let dx = Vector3::new(cos * size.x, 0.0, -sin * size.x);
+    let dy = Vector3::new(0.0, size.y, 0.0);
+    let dz = Vector3::new(sin * size.z, 0.0, cos * size.z);
+
+    add_quad(world, min, dx, dy, material.clone());
+    add_quad(world, min + dz, dx, dy, material.clone());
+    add_quad(world, min, dz, dy, material.clone());
+    add_quad(world, min + dx, dz, dy, material.clone());
+    add_quad(world, min, dx, dz, material.clone());
+    add_quad(world, min + dy, dx, dz, material);
+}
+
+pub fn cornell_box_scene() -> Scene {
+    let mut world = HittableList::default();
+
+    let red = Material::Lambertian(Color::new(0.65, 0.05, 0.05).into());
+    let white = Material::Lambertian(Color::new_eq(0.73).into());
+    let green = Material::Lambertian(Color::new(0.12, 0.45, 0.15).into());
+    let light = Material::DiffuseLight(Color::new_eq(15.0));
+
+    // The box is extended towards the camera and closed behind it
+    let front = -801.0;
+    let depth = 555.0 - front;
+    let x = Vector3::new(555.0, 0.0, 0.0);
+    let y = Vector3::new(0.0, 555.0, 0.0);
+    let z = Vector3::new(0.0, 0.0, depth);
+    let origin = Point3::new(0.0, 0.0, front);
+
+    add_quad(&mut world, origin + x, y, z, green);
+    add_quad(&mut world, origin, y, z, red);
+    add_quad(&mut world, origin, x, z, white.clone());
+    add_quad(&mut world, origin + y, x, z, white.clone());
+    add_quad(&mut world, origin + z, x, y, white.clone());
+    add_quad(&mut world, origin, x, y, white.clone());
+    add_quad(
+        &mut world,
+        Point3::new(213.0, 554.0, 227.0),
+        Vector3::new(130.0, 0.0, 0.0),
+        Vector3::new(0.0, 0.0, 105.0),
+        light,
+    );
+
+    add_block(
+        &mut world,
+        Point3::new(265.0, 0.0, 295.0),
+        Vector3::new(165.0, 330.0, 165.0),
+        15.0,
+        white.clone(),
+    );
+    add_block(
+        &mut world,
+        Point3::new(130.0, 0.0, 65.0),
+        Vector3::new(165.0, 165.0, 165.0),
+        -18.0,
+        white,
+    );
+
+    Scene {
+        image: ImageSettings {
+            width: 600,
+            aspect_ratio: 1.0,
+            samples_per_pixel: 200,
+            max_depth: 50,
+        },
+        camera: CameraSettings {
+            look_from: Point3::new(278.0, 278.0, -800.0),
+            look_at: Point3::new(278.0, 278.0, 0.0),
+            view_up: Vector3::new(0.0, 1.0, 0.0),
+            vertical_fov: 40.0,
+            aperture: 0.0,
+            focus_distance: 10.0,
+        },
+        world,
+        environment: Environment::Black,
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    }
+}
+
+/// Loads a scene file, or shows an OBJ or PLY model with `obj_scene` or `ply_scene`
+pub fn file_scene(path: &str) -> Result<Scene, Box<dyn Error>> {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => Ok(obj_scene(path)?),
+        Some("ply") => Ok(ply_scene(path)?),
+        _ => Ok(load_scene(path)?),
+    }
+}
+
+/// Wavefront OBJ model standing on a ground sphere
+pub fn obj_scene(path: &str) -> Result<Scene, ObjError> {
+    let model = load_obj(path, &Material::Lambertian(Color::new_eq(0.8).into()))?;
+    Ok(Scene {
+        warnings: model.warnings.iter().map(ToString::to_string).collect(),
+        ..model_scene(model.meshes)
+    })
+}
+
+/// Stanford PLY model standing on a ground sphere
+pub fn ply_scene(path: &str) -> Result<Scene, PlyError> {
+    let mesh = load_ply(path, &Material::Lambertian(Color::new_eq(0.8).into()))?;
+    Ok(model_scene(vec![mesh]))
+}
+
+/// Scales the meshes to a size of 2, stands them on a ground sphere at the origin and points
+/// the camera at them
+fn model_scene(mut meshes: Vec<TriangleMesh>) -> Scene {
+    let (minimum, maximum) = meshes.iter().flat_map(|mesh| &mesh.positions).fold(
+        (
+            Point3::new_eq(FloatType::INFINITY),
+            Point3::new_eq(FloatType::NEG_INFINITY),
+        ),
+        |(minimum, maximum), position| (minimum.min(position), maximum.max(position)),
+    );
+    let size = maximum - minimum;
+    let largest = size.x.max(size.y).max(size.z);
+    let scale = if largest > 0.0 && largest.is_finite() {
+        2.0 / largest
+    } else {
+        1.0
+    };
+    let bottom_center = Point3::new(
+        0.5 * (minimum.x + maximum.x),
+        minimum.y,
+        0.5 * (minimum.z + maximum.z),
+    );
+
+    let mut world = HittableList::default();
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Material::Lambertian(Color::new(0.5, 0.5, 0.5).into()),
+    )));
+    for mesh in meshes.iter_mut() {
+        for position in mesh.positions.iter_mut() {
+            *position = scale * (*position - bottom_center);
+        }
+    }
+    for mesh in meshes {
+        world.add_mesh(mesh);
+    }
+
+    let look_at = Point3::new(0.0, 0.5 * scale * size.y.max(0.0), 0.0);
+    let look_from = look_at + Vector3::new(13.0, 2.0, 3.0);
+    Scene {
+        image: ImageSettings::default(),
+        camera: CameraSettings {
+            look_from,
+            look_at,
+            aperture: 0.0,
+            focus_distance: (look_from - look_at).length(),
+            ..CameraSettings::default()
+        },
+        world,
+        environment: Environment::default(),
+        lights: Vec::new(),
+        warnings: Vec::new(),
+    }
 }
@@ -0,0 +1,234 @@
+use crate::{
+    onb::Onb,
+    utils::random_float,
+    vec::{Color, FloatType, Vector3, PI},
+};
+
+/// Angular diameter of the real sun in degrees
+pub const SUN_DIAMETER: FloatType = 0.53;
+
+/// Illuminance of the sun outside the atmosphere in klx, to match the sky luminance in kcd/m²
+const SOLAR_ILLUMINANCE: FloatType = 128.0;
+
+/// Brings luminances in kcd/m² to radiances around 1, a white surface under a high sun
+/// comes out close to white
+const SKY_SCALE: FloatType = 0.025;
+
+/// Wavelengths in micrometers the sun transmittance is evaluated at for red, green and blue
+const WAVELENGTHS: [FloatType; 3] = [0.68, 0.55, 0.44];
+
+/// Preetham et al. "A Practical Analytic Model for Daylight" with a sun disc
+///
+/// The sky dome is the analytic model, the ground below the horizon a diffuse plane lit by the
+/// sky and the sun. The sun disc is attenuated by Rayleigh and aerosol scattering on its
+/// path through the atmosphere, so it turns orange towards the horizon.
+pub struct Sky {
+    /// Unit direction towards the sun
+    sun_direction: Vector3,
+    /// Cosine of the angular radius of the sun disc
+    sun_cos_radius: FloatType,
+    /// Solid angle of the sun disc
+    sun_solid_angle: FloatType,
+    sun_radiance: Color,
+    /// Perez coefficients A to E for luminance Y and chromaticities x and y
+    perez: [[FloatType; 5]; 3],
+    /// Zenith values of Y, x and y divided by the Perez function at the zenith
+    zenith: [FloatType; 3],
+    ground_radiance: Color,
+    intensity: FloatType,
+}
+
+impl Sky {
+    /// `sun_direction` points towards the sun and must be above the horizon. The turbidity
+    /// describes the haze, from 2 (very clear) to 10 (hazy). The ground albedo colors the
+    /// lower hemisphere, the intensity scales sky and sun together.
+    pub fn new(
+        sun_direction: Vector3,
+        turbidity: FloatType,
+        ground_albedo: Color,
+        sun_diameter_degrees: FloatType,
+        intensity: FloatType,
+    ) -> Self {
+        let sun_direction = sun_direction.unit_vector();
+        let t = turbidity;
+        let theta_sun = sun_direction.y.clamp(0.0, 1.0).acos();
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let zenith_chromaticity = |m: [[FloatType; 4]; 3]| {
+            let thetas = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+            let ts = [t * t, t, 1.0];
+            (0..3)
+                .map(|i| ts[i] * (0..4).map(|j| m[i][j] * thetas[j]).sum::<FloatType>())
+                .sum::<FloatType>()
+        };
+        let zenith_x = zenith_chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = zenith_chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith = [
+            zenith_luminance.max(0.0) / perez_function(&perez[0], 0.0, theta_sun),
+            zenith_x / perez_function(&perez[1], 0.0, theta_sun),
+            zenith_y / perez_function(&perez[2], 0.0, theta_sun),
+        ];
+
+        // 1 - cos computed from the half angle, the real sun is too small for f32 otherwise
+        let sun_radius = (0.5 * sun_diameter_degrees).to_radians();
+        let one_minus_cos = 2.0 * (0.5 * sun_radius).sin().powi(2);
+        let sun_solid_angle = 2.0 * PI * one_minus_cos;
+        // Bigger discs get dimmer so the sun keeps its illuminance
+        let sun_luminance = SOLAR_ILLUMINANCE / sun_solid_angle;
+        let sun_radiance = SKY_SCALE * sun_luminance * transmittance(turbidity, theta_sun);
+
+        let mut sky = Self {
+            sun_direction,
+            sun_cos_radius: 1.0 - one_minus_cos,
+            sun_solid_angle,
+            sun_radiance,
+            perez,
+            zenith,
+            ground_radiance: Color::new_eq(0.0),
+            intensity,
+        };
+        sky.ground_radiance = ground_albedo * sky.horizontal_irradiance() / PI;
+        sky
+    }
+
+    /// Radiance arriving along the opposite of `direction`, which doesn't need to be normalized
+    pub fn radiance(&self, direction: &Vector3) -> Color {
+        let direction = direction.unit_vector();
+        let mut radiance = if direction.y < 0.0 {
+            self.ground_radiance
+        } else {
+            self.sky_radiance(&direction)
+        };
+        if direction.dot(&self.sun_direction) >= self.sun_cos_radius {
+            radiance += self.sun_radiance;
+        }
+        self.intensity * radiance
+    }
+
+    /// Picks a direction within the sun disc, returns it with its radiance and density per
+    /// solid angle. The smooth sky is left to the sampling of the materials.
+    pub fn sample(&self) -> Option<(Vector3, Color, FloatType)> {
+        let cos_theta = 1.0 - random_float() * (1.0 - self.sun_cos_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_float();
+        let local = Vector3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta);
+        let direction = Onb::from_w(&self.sun_direction).local(&local);
+        Some((
+            direction,
+            self.radiance(&direction),
+            1.0 / self.sun_solid_angle,
+        ))
+    }
+
+    /// Density of `sample` picking the direction, per solid angle
+    pub fn pdf(&self, direction: &Vector3) -> FloatType {
+        if direction.unit_vector().dot(&self.sun_direction) >= self.sun_cos_radius {
+            1.0 / self.sun_solid_angle
+        } else {
+            0.0
+        }
+    }
+
+    /// Radiance of the sky dome without the sun disc for a unit direction above the horizon
+    fn sky_radiance(&self, direction: &Vector3) -> Color {
+        // The model diverges at the horizon, hold it just above
+        let theta = direction.y.clamp(0.001, 1.0).acos();
+        let gamma = direction.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let luminance = self.zenith[0] * perez_function(&self.perez[0], theta, gamma);
+        let x = self.zenith[1] * perez_function(&self.perez[1], theta, gamma);
+        let y = self.zenith[2] * perez_function(&self.perez[2], theta, gamma);
+        SKY_SCALE * xyy_to_rgb(x, y, luminance)
+    }
+
+    /// Irradiance of sky and sun on an upward facing surface
+    fn horizontal_irradiance(&self) -> Color {
+        const STEPS_THETA: usize = 32;
+        const STEPS_PHI: usize = 64;
+        let d_theta = 0.5 * PI / STEPS_THETA as FloatType;
+        let d_phi = 2.0 * PI / STEPS_PHI as FloatType;
+        let mut irradiance = Color::new_eq(0.0);
+        for i in 0..STEPS_THETA {
+            let (sin_theta, cos_theta) = ((i as FloatType + 0.5) * d_theta).sin_cos();
+            for j in 0..STEPS_PHI {
+                let (sin_phi, cos_phi) = ((j as FloatType + 0.5) * d_phi).sin_cos();
+                let direction = Vector3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+                irradiance +=
+                    self.sky_radiance(&direction) * (cos_theta * sin_theta * d_theta * d_phi);
+            }
+        }
+        irradiance + self.sun_radiance * (self.sun_solid_angle * self.sun_direction.y)
+    }
+}
+
+/// Perez et al. luminance distribution for zenith angle `theta` and angle `gamma` to the sun
+fn perez_function(coefficients: &[FloatType; 5], theta: FloatType, gamma: FloatType) -> FloatType {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// CIE xyY to linear sRGB
+fn xyy_to_rgb(x: FloatType, y: FloatType, luminance: FloatType) -> Color {
+    if y <= 0.0 {
+        return Color::new_eq(0.0);
+    }
+    let cap_x = x / y * luminance;
+    let cap_z = (1.0 - x - y) / y * luminance;
+    Color::new(
+        3.240_454 * cap_x - 1.537_139 * luminance - 0.498_531 * cap_z,
+        -0.969_266 * cap_x + 1.876_011 * luminance + 0.041_556 * cap_z,
+        0.055_643 * cap_x - 0.204_026 * luminance + 1.057_225 * cap_z,
+    )
+    .max(&Color::new_eq(0.0))
+}
+
+/// Fraction of the sunlight that makes it through the atmosphere at zenith angle `theta`,
+/// from Rayleigh scattering and Ångström's aerosol turbidity formula
+fn transmittance(turbidity: FloatType, theta: FloatType) -> Color {
+    // Relative optical mass of the air, Kasten's formula accounts for the curved atmosphere
+    let elevation_degrees = 90.0 - theta.to_degrees();
+    let air_mass = 1.0 / (theta.cos() + 0.15 * (elevation_degrees + 3.885).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let channel = |wavelength: FloatType| {
+        let rayleigh = 0.008735 * wavelength.powf(-4.08);
+        let aerosol = beta * wavelength.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    };
+    Color::new(
+        channel(WAVELENGTHS[0]),
+        channel(WAVELENGTHS[1]),
+        channel(WAVELENGTHS[2]),
+    )
+}
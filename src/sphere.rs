@@ -1,7 +1,11 @@
 use crate::{
-    hittable::{HitRecord, Hittable},
+    aabb::Aabb,
+    hittable::{AreaLight, HitRecord, Hittable},
     material::Material,
-    vec::{FloatType, Point3},
+    onb::Onb,
+    ray::Ray,
+    utils::random_float,
+    vec::{FloatType, Point3, Vector3, PI},
 };
 
 pub struct Sphere {
@@ -18,10 +22,32 @@ impl Sphere {
             material,
         }
     }
+
+    /// Texture coordinates of a point on the unit sphere centered at the origin
+    ///
+    /// u: angle around the Y axis from X=-1, v: angle from Y=-1 to Y=+1, both mapped to [0,1]
+    fn get_sphere_uv(p: &Point3) -> (FloatType, FloatType) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    /// Derivatives of the point on the sphere along the texture coordinates of
+    /// `get_sphere_uv`, zero at the poles where u is undefined
+    fn get_sphere_tangents(p: &Point3, radius: FloatType) -> (Vector3, Vector3) {
+        let ring_radius = (p.x * p.x + p.z * p.z).sqrt();
+        if ring_radius < 1.0e-6 {
+            return (Vector3::new_eq(0.0), Vector3::new_eq(0.0));
+        }
+        let dpdu = 2.0 * PI * radius * Vector3::new(p.z, 0.0, -p.x);
+        let dpdv = PI * radius / ring_radius
+            * Vector3::new(-p.x * p.y, ring_radius * ring_radius, -p.y * p.z);
+        (dpdu, dpdv)
+    }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, r: &crate::ray::Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
         let oc = r.origin - self.center;
         let a = r.direction.dot(&r.direction);
         let half_b = oc.dot(&r.direction);
@@ -36,9 +62,9 @@ impl Hittable for Sphere {
 
         // Find the nearest root that lies in acceptable range
         let mut root = (-half_b - sqrt_discriminant) / a;
-        if root < t_min && t_max < root {
+        if root < t_min || t_max < root {
             root = (-half_b + sqrt_discriminant) / a;
-            if root < t_min && t_max < root {
+            if root < t_min || t_max < root {
                 return None;
             }
         }
@@ -46,12 +72,69 @@ impl Hittable for Sphere {
         let t = root;
         let point = r.at(t);
         let outward_normal = (point - self.center) / self.radius;
-        Some(HitRecord::new_face_normal(
+        let mut record = HitRecord::new_face_normal(
             point,
             t,
             r,
             outward_normal,
+            Self::get_sphere_uv(&outward_normal),
             &self.material,
+        );
+        (record.dpdu, record.dpdv) = Self::get_sphere_tangents(&outward_normal, self.radius);
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let radius = Vector3::new_eq(self.radius.abs());
+        Some(Aabb::new(self.center - radius, self.center + radius))
+    }
+
+    fn as_light(&self) -> Option<&dyn AreaLight> {
+        if self.material.is_emissive() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl AreaLight for Sphere {
+    /// Uniform over the cone of directions that hit the sphere, or over all directions from
+    /// inside
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> FloatType {
+        let radius_squared = self.radius * self.radius;
+        let distance_squared = (self.center - *origin).length_squared();
+        if distance_squared <= radius_squared {
+            return 1.0 / (4.0 * PI);
+        }
+        if self
+            .hit(&Ray::new(*origin, *direction), 0.001, FloatType::MAX)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        let direction = self.center - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            return Vector3::random_unit_vector();
+        }
+
+        // Uniform direction in the cone around the center
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let z = 1.0 + random_float() * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * random_float();
+        let sin_theta = (1.0 - z * z).max(0.0).sqrt();
+        Onb::from_w(&direction).local(&Vector3::new(
+            phi.cos() * sin_theta,
+            phi.sin() * sin_theta,
+            z,
         ))
     }
 }
@@ -0,0 +1,216 @@
+use std::sync::Arc;
+
+use crate::{
+    image::Framebuffer,
+    perlin::Perlin,
+    vec::{Color, FloatType, Point3, PI},
+};
+
+/// Spatially varying color, e.g. the albedo of a material
+pub trait Texture: Send + Sync {
+    /// Color at the surface coordinates `u`, `v` of the hit `point`
+    fn value(&self, u: FloatType, v: FloatType, point: &Point3) -> Color;
+}
+
+/// Constant colors can be used wherever a texture is expected
+impl From<Color> for Arc<dyn Texture> {
+    fn from(color: Color) -> Self {
+        Arc::new(SolidColor::new(color))
+    }
+}
+
+/// Same color everywhere
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: FloatType, _v: FloatType, _point: &Point3) -> Color {
+        self.color
+    }
+}
+
+/// Where the checker pattern is laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckerSpace {
+    /// Squares in texture coordinates, following the surface
+    Uv,
+    /// Cubes in world space, independent of the surface parametrization
+    World,
+}
+
+/// Alternates between two textures in squares (or cubes) with edges of length `scale`
+pub struct Checker {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    inverse_scale: FloatType,
+    space: CheckerSpace,
+}
+
+impl Checker {
+    pub fn new(
+        even: Arc<dyn Texture>,
+        odd: Arc<dyn Texture>,
+        scale: FloatType,
+        space: CheckerSpace,
+    ) -> Self {
+        Self {
+            even,
+            odd,
+            inverse_scale: 1.0 / scale,
+            space,
+        }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: FloatType, v: FloatType, point: &Point3) -> Color {
+        let cell = |x: FloatType| (self.inverse_scale * x).floor() as i64;
+        let sum = match self.space {
+            CheckerSpace::Uv => cell(u) + cell(v),
+            CheckerSpace::World => cell(point.x) + cell(point.y) + cell(point.z),
+        };
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+/// How texture coordinates outside of [0,1] are mapped onto the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Tiles the image
+    Repeat,
+    /// Tiles the image, flipping every other copy so the edges line up
+    Mirror,
+    /// Extends the edge pixels
+    Clamp,
+}
+
+impl WrapMode {
+    /// Maps a pixel index onto the image of the given size
+    fn apply(self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        match self {
+            WrapMode::Repeat => index.rem_euclid(size) as usize,
+            WrapMode::Mirror => {
+                let index = index.rem_euclid(2 * size);
+                if index < size {
+                    index as usize
+                } else {
+                    (2 * size - 1 - index) as usize
+                }
+            }
+            WrapMode::Clamp => index.clamp(0, size - 1) as usize,
+        }
+    }
+}
+
+/// Bilinearly filtered image, v = 0 is the bottom row
+pub struct ImageTexture {
+    image: Framebuffer,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn new(image: Framebuffer, wrap: WrapMode) -> Self {
+        Self { image, wrap }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: FloatType, v: FloatType, _point: &Point3) -> Color {
+        let width = self.image.width();
+        let height = self.image.height();
+        // Pixel centers are at half-integer coordinates
+        let x = u * width as FloatType - 0.5;
+        let y = (1.0 - v) * height as FloatType - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let pixels = self.image.pixels();
+        let pixel =
+            |x: i64, y: i64| pixels[self.wrap.apply(y, height) * width + self.wrap.apply(x, width)];
+        let top = (1.0 - fx) * pixel(x0, y0) + fx * pixel(x0 + 1, y0);
+        let bottom = (1.0 - fx) * pixel(x0, y0 + 1) + fx * pixel(x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+/// Procedural patterns built on Perlin noise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoisePattern {
+    /// Smooth noise
+    Noise,
+    /// Fractal sum of several octaves (fBm) of the absolute noise
+    Turbulence,
+    /// Stripes along z, distorted by turbulence
+    Marble,
+    /// Rings around the y axis, distorted by turbulence
+    Wood,
+}
+
+/// Blends between two colors following a noise pattern in world space
+///
+/// Objects have no local frame the pattern could follow, so a moved object shows another
+/// part of the pattern instead of carrying its own along.
+pub struct NoiseTexture {
+    perlin: Perlin,
+    pattern: NoisePattern,
+    /// Frequency of the pattern, larger values give finer detail
+    scale: FloatType,
+    octaves: usize,
+    low: Color,
+    high: Color,
+}
+
+impl NoiseTexture {
+    /// The same seed always gives the same pattern
+    pub fn new(
+        pattern: NoisePattern,
+        scale: FloatType,
+        octaves: usize,
+        low: Color,
+        high: Color,
+        seed: u64,
+    ) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            pattern,
+            scale,
+            octaves,
+            low,
+            high,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: FloatType, _v: FloatType, point: &Point3) -> Color {
+        let p = self.scale * *point;
+        let t = match self.pattern {
+            NoisePattern::Noise => 0.5 * (1.0 + self.perlin.noise(&p)),
+            NoisePattern::Turbulence => self.perlin.turbulence(&p, self.octaves),
+            NoisePattern::Marble => {
+                let phase = p.z + 4.0 * self.perlin.turbulence(&p, self.octaves);
+                0.5 * (1.0 + (PI * phase).sin())
+            }
+            NoisePattern::Wood => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                let rings = 4.0 * radius + self.perlin.turbulence(&p, self.octaves);
+                rings - rings.floor()
+            }
+        };
+        let t = t.clamp(0.0, 1.0);
+        (1.0 - t) * self.low + t * self.high
+    }
+}
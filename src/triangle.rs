@@ -0,0 +1,306 @@
+use crate::{
+    aabb::Aabb,
+    hittable::{AreaLight, HitRecord, Hittable},
+    material::Material,
+    ray::Ray,
+    utils::random_float,
+    vec::{FloatType, Point3, Vector3},
+};
+
+/// Minimum thickness of triangle bounding boxes, so axis-aligned triangles don't get flat boxes
+const BOX_PADDING: FloatType = 1.0e-4;
+
+/// Single triangle with its own material
+pub struct Triangle {
+    vertices: [Point3; 3],
+    material: Material,
+}
+
+impl Triangle {
+    /// Vertices are expected in counter-clockwise order when looking at the front face
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Material) -> Self {
+        Self {
+            vertices: [a, b, c],
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: FloatType, t_max: FloatType) -> Option<HitRecord<'_>> {
+        let [a, b, c] = self.vertices;
+        let (t, barycentric) = intersect(r, &self.vertices, t_min, t_max)?;
+        let outward_normal = (b - a).cross(&(c - a)).unit_vector();
+        let mut record = HitRecord::new_face_normal(
+            r.at(t),
+            t,
+            r,
+            outward_normal,
+            (barycentric[1], barycentric[2]),
+            &self.material,
+        );
+        // The texture coordinates are the barycentric weights of b and c
+        record.dpdu = b - a;
+        record.dpdv = c - a;
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(triangle_box(&self.vertices))
+    }
+
+    fn as_light(&self) -> Option<&dyn AreaLight> {
+        if self.material.is_emissive() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+impl AreaLight for Triangle {
+    fn pdf_value(&self, origin: &Point3, direction: &Vector3) -> FloatType {
+        pdf_towards(&self.vertices, origin, direction)
+    }
+
+    fn random(&self, origin: &Point3) -> Vector3 {
+        random_towards(&self.vertices, origin)
+    }
+}
+
+/// Direction from `origin` to a uniformly distributed point on the triangle
+pub fn random_towards(vertices: &[Point3; 3], origin: &Point3) -> Vector3 {
+    let [a, b, c] = *vertices;
+    let s = random_float().sqrt();
+    let r = random_float();
+    let point = (1.0 - s) * a + (s * (1.0 - r)) * b + (s * r) * c;
+    point - *origin
+}
+
+/// Density of `random_towards` picking the direction, per solid angle
+pub fn pdf_towards(vertices: &[Point3; 3], origin: &Point3, direction: &Vector3) -> FloatType {
+    let (t, _) = match intersect(
+        &Ray::new(*origin, *direction),
+        vertices,
+        0.001,
+        FloatType::MAX,
+    ) {
+        Some(hit) => hit,
+        None => return 0.0,
+    };
+    let [a, b, c] = *vertices;
+    let normal = (b - a).cross(&(c - a));
+    let area = 0.5 * normal.length();
+    let cosine = direction.dot(&normal).abs() / (direction.length() * normal.length());
+    if cosine <= 0.0 || area <= 0.0 {
+        return 0.0;
+    }
+    let distance_squared = t * t * direction.length_squared();
+    distance_squared / (cosine * area)
+}
+
+/// Box around the three vertices, padded to a minimum thickness
+pub fn triangle_box(vertices: &[Point3; 3]) -> Aabb {
+    let [a, b, c] = vertices;
+    let pad = Vector3::new_eq(BOX_PADDING);
+    Aabb::new(a.min(b).min(c) - pad, a.max(b).max(c) + pad)
+}
+
+/// Watertight ray/triangle intersection (Woop, Benthin, Wald 2013)
+///
+/// Rays passing exactly through a shared edge or vertex hit exactly one of the adjacent
+/// triangles, so meshes neither leak light through their seams nor hit them twice.
+/// Returns the ray parameter and the barycentric weights of the three vertices.
+pub fn intersect(
+    r: &Ray,
+    vertices: &[Point3; 3],
+    t_min: FloatType,
+    t_max: FloatType,
+) -> Option<(FloatType, [FloatType; 3])> {
+    let d = r.direction;
+
+    // Permute the axes so that the ray direction is largest along z
+    let kz = if d.x.abs() > d.y.abs() && d.x.abs() > d.z.abs() {
+        0
+    } else if d.y.abs() > d.z.abs() {
+        1
+    } else {
+        2
+    };
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if d[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear so the ray points along +z
+    let sx = d[kx] / d[kz];
+    let sy = d[ky] / d[kz];
+    let sz = 1.0 / d[kz];
+
+    let rel = [
+        vertices[0] - r.origin,
+        vertices[1] - r.origin,
+        vertices[2] - r.origin,
+    ];
+    let x = |p: &Vector3| p[kx] - sx * p[kz];
+    let y = |p: &Vector3| p[ky] - sy * p[kz];
+    let (ax, ay) = (x(&rel[0]), y(&rel[0]));
+    let (bx, by) = (x(&rel[1]), y(&rel[1]));
+    let (cx, cy) = (x(&rel[2]), y(&rel[2]));
+
+    // Scaled barycentric coordinates as 2D edge functions
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+
+    // Fall back to double precision on the edges
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        let (ax, ay, bx, by, cx, cy) = (
+            ax as f64, ay as f64, bx as f64, by as f64, cx as f64, cy as f64,
+        );
+        u = (cx * by - cy * bx) as FloatType;
+        v = (ax * cy - ay * cx) as FloatType;
+        w = (bx * ay - by * ax) as FloatType;
+    }
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    // Adjacent triangles compute the same edge functions with opposite signs, on an edge
+    // only the triangle that owns it reports the hit. Ownership is a top-left rule like in
+    // rasterization, with the edge oriented by the facing of the triangle.
+    let owns = |from: (FloatType, FloatType), to: (FloatType, FloatType)| {
+        let ex = (to.0 - from.0) * det.signum();
+        let ey = (to.1 - from.1) * det.signum();
+        ey > 0.0 || (ey == 0.0 && ex < 0.0)
+    };
+    if (u == 0.0 && !owns((bx, by), (cx, cy)))
+        || (v == 0.0 && !owns((cx, cy), (ax, ay)))
+        || (w == 0.0 && !owns((ax, ay), (bx, by)))
+    {
+        return None;
+    }
+
+    let az = sz * rel[0][kz];
+    let bz = sz * rel[1][kz];
+    let cz = sz * rel[2][kz];
+    let t = (u * az + v * bz + w * cz) / det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some((t, [u / det, v / det, w / det]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hits(triangles: &[[Point3; 3]], r: &Ray) -> usize {
+        triangles
+            .iter()
+            .filter(|vertices| intersect(r, vertices, 0.0, FloatType::INFINITY).is_some())
+            .count()
+    }
+
+    /// Rays from above and below at the given points of the plane z = 0
+    fn rays_through(points: &[Point3]) -> Vec<Ray> {
+        let origins = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(0.3, -0.7, 1.1),
+            Vector3::new(-2.0, 0.5, -0.4),
+            Vector3::new(0.1, 3.0, 0.2),
+        ];
+        points
+            .iter()
+            .flat_map(|&point| {
+                origins
+                    .iter()
+                    .map(move |&offset| Ray::new(point + offset, -offset))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shared_edge_hits_once() {
+        let [a, b, c, d] = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let quad = [[a, b, c], [a, c, d]];
+        let points: Vec<Point3> = (1..16)
+            .map(|i| Point3::new_eq(i as FloatType / 16.0))
+            .map(|point| Point3::new(point.x, point.y, 0.0))
+            .collect();
+        for r in rays_through(&points) {
+            assert_eq!(hits(&quad, &r), 1, "{:?} {:?}", r.origin, r.direction);
+        }
+    }
+
+    #[test]
+    fn shared_vertex_hits_once() {
+        // Fan of eight triangles around the origin
+        let center = Point3::new(0.0, 0.0, 0.0);
+        let ring = [
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+            (-1.0, 1.0),
+            (-1.0, 0.0),
+            (-1.0, -1.0),
+            (0.0, -1.0),
+            (1.0, -1.0),
+        ]
+        .map(|(x, y)| Point3::new(x, y, 0.0));
+        let fan: Vec<[Point3; 3]> = (0..ring.len())
+            .map(|i| [center, ring[i], ring[(i + 1) % ring.len()]])
+            .collect();
+        for r in rays_through(&[center]) {
+            assert_eq!(hits(&fan, &r), 1, "{:?} {:?}", r.origin, r.direction);
+        }
+    }
+
+    #[test]
+    fn parallel_ray_misses() {
+        let vertices = [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ];
+        let above = Ray::new(Point3::new(-1.0, 0.2, 0.5), Vector3::new(1.0, 0.0, 0.0));
+        let in_plane = Ray::new(Point3::new(-1.0, 0.2, 0.0), Vector3::new(1.0, 0.0, 0.0));
+        for r in [above, in_plane] {
+            assert!(intersect(&r, &vertices, 0.0, FloatType::INFINITY).is_none());
+        }
+    }
+
+    #[test]
+    fn back_face_hit() {
+        let triangle = Triangle::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+            Material::Lambertian(Vector3::new_eq(0.5).into()),
+        );
+        let front = Ray::new(Point3::new(0.2, 0.2, 1.0), Vector3::new(0.0, 0.0, -1.0));
+        let back = Ray::new(Point3::new(0.2, 0.2, -1.0), Vector3::new(0.0, 0.0, 1.0));
+
+        let hit = triangle.hit(&front, 0.001, FloatType::INFINITY).unwrap();
+        assert!(hit.facing_front);
+        assert_eq!(hit.normal.z, 1.0);
+        let hit = triangle.hit(&back, 0.001, FloatType::INFINITY).unwrap();
+        assert!(!hit.facing_front);
+        assert_eq!(hit.normal.z, -1.0);
+        assert_eq!(hit.t, 1.0);
+    }
+}
@@ -1,15 +1,49 @@
-use crate::vec::FloatType;
+use std::cell::RefCell;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::vec::{Color, FloatType};
+
+thread_local! {
+    /// Random number generator of the current thread, see `reseed_rng`
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Restarts the random sequence of the current thread from a fixed seed
+///
+/// Rendering reseeds per scanline, so the image only depends on the seed and not on how
+/// the scanlines are distributed over the threads.
+pub fn reseed_rng(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
 
 pub fn random_float() -> FloatType {
-    use rand::{thread_rng, Rng};
+    RNG.with(|rng| rng.borrow_mut().gen())
+}
 
-    let mut rng = thread_rng();
-    rng.gen()
+/// Converts an sRGB encoded channel in [0,1] to linear intensity
+pub fn srgb_to_linear(value: FloatType) -> FloatType {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
 }
 
-pub fn random_float_range(min: FloatType, max: FloatType) -> FloatType {
-    use rand::{thread_rng, Rng};
+/// Converts a linear intensity in [0,1] to an sRGB encoded channel
+pub fn linear_to_srgb(value: FloatType) -> FloatType {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
 
-    let mut rng = thread_rng();
-    rng.gen_range(min..max)
+/// Perceived brightness of a linear Rec. 709 color
+pub fn luminance(color: &Color) -> FloatType {
+    0.2126 * color.r() + 0.7152 * color.g() + 0.0722 * color.b()
+}
+
+pub fn random_float_range(min: FloatType, max: FloatType) -> FloatType {
+    RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
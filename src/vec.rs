@@ -1,7 +1,4 @@
-use std::{
-    iter::Sum,
-    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub},
-};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
 
 use crate::utils::{random_float, random_float_range};
 
@@ -29,7 +26,7 @@ impl Vector3 {
 
     /// Create a Vector from same value (x=y=z)
     pub fn new_eq(x: FloatType) -> Self {
-        Vector3 { x: x, y: x, z: x }
+        Vector3 { x, y: x, z: x }
     }
 
     pub fn random() -> Self {
@@ -45,7 +42,6 @@ impl Vector3 {
     }
 
     /// Find a random point in a unit radius sphere
-
     pub fn random_in_unit_sphere() -> Self {
         loop {
             let p = Self::random_range(-1.0, 1.0);
@@ -61,7 +57,7 @@ impl Vector3 {
 
     pub fn random_in_hemisphere(normal: &Vector3) -> Self {
         let in_unit_sphere = Self::random_in_unit_sphere();
-        if in_unit_sphere.dot(&normal) > 0.0
+        if in_unit_sphere.dot(normal) > 0.0
         // in the same hemisphere as the normal
         {
             in_unit_sphere
@@ -70,6 +66,19 @@ impl Vector3 {
         }
     }
 
+    /// Random direction around +z with a density proportional to its z component
+    pub fn random_cosine_direction() -> Self {
+        let r1 = random_float();
+        let r2 = random_float();
+        let phi = 2.0 * PI * r1;
+        let radius = r2.sqrt();
+        Vector3::new(
+            phi.cos() * radius,
+            phi.sin() * radius,
+            (1.0 - r2).max(0.0).sqrt(),
+        )
+    }
+
     pub fn random_in_unit_disk() -> Self {
         loop {
             let p = Vector3::new(
@@ -122,6 +131,16 @@ impl Vector3 {
         r_out_perp + r_out_parallel
     }
 
+    /// Component-wise minimum of two vectors
+    pub fn min(&self, rhs: &Self) -> Self {
+        Vector3::new(self.x.min(rhs.x), self.y.min(rhs.y), self.z.min(rhs.z))
+    }
+
+    /// Component-wise maximum of two vectors
+    pub fn max(&self, rhs: &Self) -> Self {
+        Vector3::new(self.x.max(rhs.x), self.y.max(rhs.y), self.z.max(rhs.z))
+    }
+
     pub fn unit_vector(&self) -> Self {
         self / self.length()
     }
@@ -138,6 +157,7 @@ impl Vector3 {
         self.z
     }
 
+    #[allow(unused)]
     pub fn print_color(&self, samples_per_pixel: usize) -> (u8, u8, u8) {
         let mut r = self.r();
         let mut g = self.g();
@@ -170,6 +190,20 @@ impl core::fmt::Display for Vector3 {
     }
 }
 
+/// Access the components by axis (0 = x, 1 = y, 2 = z)
+impl Index<usize> for Vector3 {
+    type Output = FloatType;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vector3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl Neg for Vector3 {
     type Output = Self;
 